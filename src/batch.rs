@@ -0,0 +1,78 @@
+//! Many games played by a strategy without a terminal, for comparing
+//! strategies and tuning their heuristics
+
+use std::collections::BTreeMap;
+use std::fmt;
+
+use game::Game;
+use solver::Strategy;
+use ui::{NullUI, UI};
+
+/// How a batch of games went
+pub struct Stats {
+    pub games: usize,
+    pub total_score: usize,
+    /// How many games ended with each best tile
+    pub max_tiles: BTreeMap<usize, usize>,
+}
+
+impl Stats {
+    pub fn mean_score(&self) -> f64 {
+        if self.games == 0 {
+            return 0.0;
+        }
+        self.total_score as f64 / self.games as f64
+    }
+
+    /// How many games got a tile of at least `tile`
+    pub fn reached(&self, tile: usize) -> usize {
+        self.max_tiles.range(tile..).map(|(_, &games)| games).sum()
+    }
+}
+
+impl fmt::Display for Stats {
+    /// A table of the best tiles, with the share of games that got at least as far
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "Games: {}  Mean score: {:.1}", self.games, self.mean_score())?;
+        writeln!(f, "{:>9}  {:>6}  {:>7}", "Best tile", "Games", "Reached")?;
+        for (&tile, &games) in &self.max_tiles {
+            let percent = self.reached(tile) * 100 / self.games.max(1);
+            writeln!(f, "{:>9}  {:>6}  {:>6}%", tile, games, percent)?;
+        }
+        Ok(())
+    }
+}
+
+/// Plays `n` games with `strategy`, each until it's lost, the first with
+/// `seed` and each one after with the next seed; `new_game` sets up a game
+/// from its seed with whatever rules are being tried
+pub fn run_batch<F>(strategy: &Strategy, n: usize, seed: u64, new_game: F) -> Stats
+    where F: Fn(&UI, u64) -> Game
+{
+    let mut stats = Stats {
+        games: 0,
+        total_score: 0,
+        max_tiles: BTreeMap::new(),
+    };
+    let ui = NullUI::new(::std::iter::empty());
+    for i in 0..n {
+        let mut game = new_game(&ui, seed.wrapping_add(i as u64));
+        game.animation_ms = 0;
+        // past the target, to see how far the strategy gets
+        game.continue_after_win = true;
+        game.start();
+        game.finish_animation();
+        while let Some(direc) = strategy.choose(&game.board) {
+            let moves = game.moves;
+            game.step(direc.key());
+            // a move the game refuses would be chosen again forever
+            if game.moves == moves {
+                break;
+            }
+        }
+        stats.games += 1;
+        stats.total_score += game.board.score;
+        *stats.max_tiles.entry(game.board.max_tile()).or_insert(0) += 1;
+    }
+    stats
+}
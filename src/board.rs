@@ -0,0 +1,1374 @@
+//! The grid of tiles and the rules for moving them
+
+use std::fmt;
+
+use rand::{Rng, SeedableRng, StdRng};
+use rand::distributions::{IndependentSample, Range};
+use serde_json;
+
+use ui::Key;
+
+/// Arrangements a shuffle tries before giving up
+const SHUFFLE_TRIES: usize = 16;
+
+/// Number of columns and rows of tiles
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct BoardSize {
+    pub cols: usize,
+    pub rows: usize,
+}
+
+impl Default for BoardSize {
+    fn default() -> BoardSize {
+        BoardSize { cols: 4, rows: 4 }
+    }
+}
+
+#[derive(PartialEq, Eq, Hash, Clone, Copy, Debug)]
+pub enum Direction {
+    Up,
+    Down,
+    Left,
+    Right,
+    /// Only on boards with `Board::diagonals`
+    UpLeft,
+    UpRight,
+    DownLeft,
+    DownRight,
+}
+
+/// The moves `Board::diagonals` adds
+pub const DIAGONALS: [Direction; 4] =
+    [Direction::UpLeft, Direction::UpRight, Direction::DownLeft, Direction::DownRight];
+
+impl Direction {
+    /// The moves every board has, see `Board::directions` for those of a given one
+    pub fn all() -> [Direction; 4] {
+        [Direction::Up, Direction::Down, Direction::Left, Direction::Right]
+    }
+
+    /// The direction that moves back the way this one went
+    pub fn opposite(self) -> Direction {
+        match self {
+            Direction::Up => Direction::Down,
+            Direction::Down => Direction::Up,
+            Direction::Left => Direction::Right,
+            Direction::Right => Direction::Left,
+            Direction::UpLeft => Direction::DownRight,
+            Direction::UpRight => Direction::DownLeft,
+            Direction::DownLeft => Direction::UpRight,
+            Direction::DownRight => Direction::UpLeft,
+        }
+    }
+
+    pub fn offset(self) -> (i32, i32) {
+        match self {
+            Direction::Up => (0, -1),
+            Direction::Down => (0, 1),
+            Direction::Left => (-1, 0),
+            Direction::Right => (1, 0),
+            Direction::UpLeft => (-1, -1),
+            Direction::UpRight => (1, -1),
+            Direction::DownLeft => (-1, 1),
+            Direction::DownRight => (1, 1),
+        }
+    }
+
+    /// The key that moves in this direction, diagonals are where they are on
+    /// a number pad: q, e and c around w, a, s, d already quit, remove and
+    /// continue, and z is often bound to undo
+    pub fn key(self) -> Key {
+        match self {
+            Direction::Up => Key::Up,
+            Direction::Down => Key::Down,
+            Direction::Left => Key::Left,
+            Direction::Right => Key::Right,
+            Direction::UpLeft => Key::Char('7'),
+            Direction::UpRight => Key::Char('9'),
+            Direction::DownLeft => Key::Char('1'),
+            Direction::DownRight => Key::Char('3'),
+        }
+    }
+
+    /// Reads a key written by `key`
+    pub fn from_key(key: Key) -> Option<Direction> {
+        Direction::all()
+            .iter()
+            .chain(DIAGONALS.iter())
+            .cloned()
+            .find(|direc| direc.key() == key)
+    }
+
+    /// An arrow pointing this way
+    pub fn arrow(self) -> char {
+        match self {
+            Direction::Up => '↑',
+            Direction::Down => '↓',
+            Direction::Left => '←',
+            Direction::Right => '→',
+            Direction::UpLeft => '↖',
+            Direction::UpRight => '↗',
+            Direction::DownLeft => '↙',
+            Direction::DownRight => '↘',
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Direction::Up => "up",
+            Direction::Down => "down",
+            Direction::Left => "left",
+            Direction::Right => "right",
+            Direction::UpLeft => "up-left",
+            Direction::UpRight => "up-right",
+            Direction::DownLeft => "down-left",
+            Direction::DownRight => "down-right",
+        }
+    }
+
+    /// Reads a name written by `name`
+    pub fn from_name(name: &str) -> Option<Direction> {
+        match name {
+            "up" => Some(Direction::Up),
+            "down" => Some(Direction::Down),
+            "left" => Some(Direction::Left),
+            "right" => Some(Direction::Right),
+            "up-left" => Some(Direction::UpLeft),
+            "up-right" => Some(Direction::UpRight),
+            "down-left" => Some(Direction::DownLeft),
+            "down-right" => Some(Direction::DownRight),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Copy, Clone)]
+pub struct Tile {
+    _value: usize,
+    _value_old: usize,
+    /// the tile changed, but the old value should be shown before animation is done
+    _pending: bool,
+}
+
+impl Tile {
+    pub fn new() -> Tile {
+        Tile {
+            _value: 0,
+            _value_old: 0,
+            _pending: false,
+        }
+    }
+
+    pub fn from_value(value: usize) -> Tile {
+        Tile {
+            _value: value,
+            _value_old: 0,
+            _pending: false,
+        }
+    }
+
+    pub fn set(&mut self, val: usize) {
+        self._value_old = self._value;
+        self._value = val;
+    }
+
+    /// The value once any pending animation is done
+    pub fn value(&self) -> usize {
+        self._value
+    }
+
+    pub fn get(&self) -> usize {
+        if self._pending {
+            self._value_old
+        } else {
+            self._value
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self._value == 0
+    }
+
+    pub fn is_pending(&self) -> bool {
+        self._pending
+    }
+
+    pub fn set_pending(&mut self, pending: bool) {
+        self._pending = pending;
+    }
+}
+
+impl fmt::Display for Tile {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.get())
+    }
+}
+
+/// Tiles are equal when their values are, whether or not an animation still
+/// shows an older value; the rules only ever look at this value
+impl PartialEq for Tile {
+    fn eq(&self, other: &Tile) -> bool {
+        self._value == other._value
+    }
+
+    fn ne(&self, other: &Tile) -> bool {
+        self._value != other._value
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+pub enum State {
+    Playing,
+    Won,
+    Lost,
+}
+
+/// How a merge adds to the score
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum ScoringRule {
+    /// The value of the merged tile, as in the original game
+    Classic,
+    /// One point a merge
+    MergeCount,
+    /// The power of two of the merged tile, so 11 for 2048
+    Log2,
+}
+
+impl ScoringRule {
+    /// Points for a merge into a tile of `value`
+    pub fn points(self, value: usize) -> usize {
+        match self {
+            ScoringRule::Classic => value,
+            ScoringRule::MergeCount => 1,
+            ScoringRule::Log2 => value.trailing_zeros() as usize,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            ScoringRule::Classic => "classic",
+            ScoringRule::MergeCount => "merges",
+            ScoringRule::Log2 => "log2",
+        }
+    }
+
+    /// Reads a name written by `name`
+    pub fn from_name(name: &str) -> Option<ScoringRule> {
+        match name {
+            "classic" => Some(ScoringRule::Classic),
+            "merges" => Some(ScoringRule::MergeCount),
+            "log2" => Some(ScoringRule::Log2),
+            _ => None,
+        }
+    }
+}
+
+/// Where new tiles may appear
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum SpawnPolicy {
+    /// Any empty cell
+    Uniform,
+    /// Only along the edge that lies in a direction
+    EdgeOnly(Direction),
+    /// Only in the four corners
+    CornerOnly,
+}
+
+impl SpawnPolicy {
+    /// Whether a new tile may appear at `p` on a board of `size`
+    pub fn allows(self, p: Point, size: BoardSize) -> bool {
+        let (right, bottom) = (size.cols - 1, size.rows - 1);
+        match self {
+            SpawnPolicy::Uniform => true,
+            // a diagonal takes in both of its edges
+            SpawnPolicy::EdgeOnly(direc) => {
+                let (dx, dy) = direc.offset();
+                (dx < 0 && p.x == 0) || (dx > 0 && p.x == right)
+                    || (dy < 0 && p.y == 0) || (dy > 0 && p.y == bottom)
+            }
+            SpawnPolicy::CornerOnly => (p.x == 0 || p.x == right) && (p.y == 0 || p.y == bottom),
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            SpawnPolicy::Uniform => "uniform",
+            SpawnPolicy::EdgeOnly(Direction::Up) => "top",
+            SpawnPolicy::EdgeOnly(Direction::Down) => "bottom",
+            SpawnPolicy::EdgeOnly(Direction::Left) => "left",
+            SpawnPolicy::EdgeOnly(Direction::Right) => "right",
+            SpawnPolicy::EdgeOnly(Direction::UpLeft) => "top-left",
+            SpawnPolicy::EdgeOnly(Direction::UpRight) => "top-right",
+            SpawnPolicy::EdgeOnly(Direction::DownLeft) => "bottom-left",
+            SpawnPolicy::EdgeOnly(Direction::DownRight) => "bottom-right",
+            SpawnPolicy::CornerOnly => "corners",
+        }
+    }
+
+    /// Reads a name written by `name`
+    pub fn from_name(name: &str) -> Option<SpawnPolicy> {
+        match name {
+            "uniform" => Some(SpawnPolicy::Uniform),
+            "top" => Some(SpawnPolicy::EdgeOnly(Direction::Up)),
+            "bottom" => Some(SpawnPolicy::EdgeOnly(Direction::Down)),
+            "left" => Some(SpawnPolicy::EdgeOnly(Direction::Left)),
+            "right" => Some(SpawnPolicy::EdgeOnly(Direction::Right)),
+            "top-left" => Some(SpawnPolicy::EdgeOnly(Direction::UpLeft)),
+            "top-right" => Some(SpawnPolicy::EdgeOnly(Direction::UpRight)),
+            "bottom-left" => Some(SpawnPolicy::EdgeOnly(Direction::DownLeft)),
+            "bottom-right" => Some(SpawnPolicy::EdgeOnly(Direction::DownRight)),
+            "corners" => Some(SpawnPolicy::CornerOnly),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub struct Point {
+    pub x: usize,
+    pub y: usize,
+}
+
+pub struct Movement {
+    pub tile: Tile,
+    pub pold: Point,
+    pub pnew: Point,
+    /// The tile merged where it stopped
+    pub merged: bool,
+}
+
+pub struct Appearing {
+    pub position: Point,
+    pub value: usize,
+}
+
+/// The grid of tiles and the rules of the game, independent of any UI
+#[derive(Clone)]
+pub struct Board {
+    pub size: BoardSize,
+    /// Tiles indexed by column then row
+    pub grid: Vec<Vec<Tile>>,
+    pub state: State,
+    pub score: usize,
+    /// Points the game started with, a handicap that doesn't count towards
+    /// a best score, see `earned`
+    pub start_score: usize,
+    /// The tile value that wins the game once it appears on the grid
+    pub win_target: usize,
+    /// Chance of a new tile being a 4 rather than a 2
+    pub four_prob: f64,
+    pub moved: bool,
+    /// Merges made by the latest move
+    pub merges: usize,
+    /// Largest tile formed by a merge in the latest move
+    pub largest_merge: usize,
+    /// The seed `rng` started from, the same seed and keys replay the same game
+    pub seed: u64,
+    /// Source of all randomness in the game
+    pub rng: StdRng,
+    /// Drawn from `rng` ahead of time, decides whether the next new tile is a 4
+    pub spawn_roll: f64,
+    /// Tiles fall to the bottom of their columns after every move
+    pub gravity: bool,
+    /// What each merge scores
+    pub scoring: ScoringRule,
+    /// Where new tiles may appear
+    pub spawn: SpawnPolicy,
+    /// Opposite edges are joined, see `merge_across_seam`
+    pub wrap: bool,
+    /// Tiles can move diagonally as well, see `DIAGONALS`
+    pub diagonals: bool,
+    /// Cells no tile can enter, move through or merge across
+    pub walls: Vec<Point>,
+}
+
+impl Board {
+    pub fn new(size: BoardSize, seed: u64) -> Board {
+        let mut board = Board {
+            size: size,
+            grid: vec![vec![Tile::new(); size.rows]; size.cols],
+            state: State::Playing,
+            score: 0,
+            start_score: 0,
+            win_target: 2048,
+            four_prob: 0.1,
+            moved: false,
+            merges: 0,
+            largest_merge: 0,
+            seed: seed,
+            rng: StdRng::from_seed(&[seed as usize, (seed >> 32) as usize][..]),
+            spawn_roll: 0.0,
+            gravity: false,
+            scoring: ScoringRule::Classic,
+            spawn: SpawnPolicy::Uniform,
+            wrap: false,
+            diagonals: false,
+            walls: Vec::new(),
+        };
+        board.reseed(seed);
+        board
+    }
+
+    /// Starts the new tiles over from `seed`, as a new board with it would
+    pub fn reseed(&mut self, seed: u64) {
+        self.seed = seed;
+        self.rng = StdRng::from_seed(&[seed as usize, (seed >> 32) as usize][..]);
+        // drawn in the same order as when each tile drew its own, so seeds
+        // still give the games they always did
+        self.spawn_roll = Range::new(0f64, 1.).ind_sample(&mut self.rng);
+    }
+
+    /// Points scored in play, without those the game started with
+    pub fn earned(&self) -> usize {
+        self.score.saturating_sub(self.start_score)
+    }
+
+    /// Value of the next new tile
+    pub fn next_spawn(&self) -> usize {
+        // counted from the top so 1.0 always gives a 4 and 0.0 never does
+        if self.spawn_roll >= 1.0 - self.four_prob { 4 } else { 2 }
+    }
+
+    /// Picks an empty cell for a new tile, the caller places it
+    pub fn add_tile(&mut self) -> Option<Appearing> {
+        let mut empty = Vec::new();
+        for i in 0..self.size.cols {
+            for j in 0..self.size.rows {
+                if self.grid[i][j].is_empty() && !self.is_wall(i, j) {
+                    empty.push(Point { x: i, y: j });
+                }
+            }
+        }
+
+        if empty.is_empty() {
+            return None;
+        }
+        // once the allowed cells are full a tile goes anywhere, so the game can still end
+        let size = self.size;
+        let policy = self.spawn;
+        if empty.iter().any(|&p| policy.allows(p, size)) {
+            empty.retain(|&p| policy.allows(p, size));
+        }
+
+        let value = self.next_spawn();
+        // every empty cell is equally likely, however few are left
+        let position = empty[self.rng.gen_range(0, empty.len())];
+        self.spawn_roll = Range::new(0f64, 1.).ind_sample(&mut self.rng);
+        Some(Appearing {
+            value: value,
+            position: position,
+        })
+    }
+
+    pub fn can_move(&self) -> bool {
+        !self.available_moves().is_empty()
+    }
+
+    /// Whether the win target is on the board
+    pub fn is_won(&self) -> bool {
+        self.has_tile(self.win_target)
+    }
+
+    /// Whether no move would change the board
+    pub fn is_lost(&self) -> bool {
+        !self.can_move()
+    }
+
+    pub fn is_wall(&self, x: usize, y: usize) -> bool {
+        self.walls.iter().any(|wall| wall.x == x && wall.y == y)
+    }
+
+    /// Directions tiles can move in on this board
+    pub fn directions(&self) -> Vec<Direction> {
+        let mut directions = Direction::all().to_vec();
+        if self.diagonals {
+            directions.extend_from_slice(&DIAGONALS);
+        }
+        directions
+    }
+
+    /// Directions that would change the board
+    pub fn available_moves(&self) -> Vec<Direction> {
+        self.directions()
+            .into_iter()
+            .filter(|&direc| self.clone_and_move(direc).1)
+            .collect()
+    }
+
+    /// A copy of the board moved in `direc`, and whether anything moved;
+    /// the board itself is left as it is
+    pub fn clone_and_move(&self, direc: Direction) -> (Board, bool) {
+        let mut trial = self.clone();
+        trial.moved = false;
+        trial.move_all(direc);
+        let moved = trial.moved;
+        (trial, moved)
+    }
+
+    /// Counts a merge into a tile of `value` and scores it by the rule
+    fn count_merge(&mut self, value: usize) {
+        self.score += self.scoring.points(value);
+        self.merges += 1;
+        if value > self.largest_merge {
+            self.largest_merge = value;
+        }
+    }
+
+    /// Moves the tiles to random cells, keeping their values
+    ///
+    /// No two tiles that would merge into the win target or more end up side
+    /// by side. When no arrangement within `SHUFFLE_TRIES` manages that the
+    /// board stays as it was and false is returned.
+    pub fn shuffle(&mut self) -> bool {
+        let rows = self.size.rows;
+        // walls stay where they are, so do the cells around them
+        let cells: Vec<(usize, usize)> = (0..self.size.cols)
+            .flat_map(|col| (0..rows).map(move |row| (col, row)))
+            .filter(|&(col, row)| !self.is_wall(col, row))
+            .collect();
+        let mut values: Vec<usize> = cells.iter().map(|&(col, row)| self.grid[col][row].value()).collect();
+        for _ in 0..SHUFFLE_TRIES {
+            self.rng.shuffle(&mut values);
+            // indexed by column then row, like the grid
+            let mut placed = vec![0; self.size.cols * rows];
+            for (&(col, row), &value) in cells.iter().zip(values.iter()) {
+                placed[col * rows + row] = value;
+            }
+            let wins = |col: usize, row: usize, other: usize| {
+                let value = placed[col * rows + row];
+                value > 0 && value == placed[other] && value * 2 >= self.win_target
+            };
+            let trivial = (0..self.size.cols).any(|col| (0..rows).any(|row| {
+                (col + 1 < self.size.cols && wins(col, row, (col + 1) * rows + row))
+                    || (row + 1 < rows && wins(col, row, col * rows + row + 1))
+            }));
+            if !trivial {
+                for (&(col, row), &value) in cells.iter().zip(values.iter()) {
+                    self.grid[col][row] = Tile::from_value(value);
+                }
+                return true;
+            }
+        }
+        false
+    }
+
+    /// The tiles as a character per cell in column order: the power of two
+    /// in base 36, 0 for an empty cell, such as `1200...` for a 2 and a 4 at
+    /// the top of the first column
+    pub fn to_code(&self) -> String {
+        self.grid.iter()
+            .flat_map(|column| column.iter())
+            .map(|tile| match tile.value() {
+                0 => '0',
+                value => std::char::from_digit(value.trailing_zeros(), 36).unwrap_or('?'),
+            })
+            .collect()
+    }
+
+    /// The tiles as a number, 4 bits each holding the power of two, in
+    /// column order
+    ///
+    /// None for boards of more than 16 cells or with a tile past 32768, or
+    /// any other value that isn't a power of two. Only the tiles go in, two
+    /// boards alike in everything but their score pack the same.
+    pub fn pack(&self) -> Option<u64> {
+        if self.size.cols * self.size.rows > 16 {
+            return None;
+        }
+        let mut packed = 0u64;
+        for tile in self.grid.iter().flat_map(|column| column.iter()) {
+            let value = tile.value();
+            let power = if value == 0 { 0 } else { value.trailing_zeros() as u64 };
+            if power > 15 || (value != 0 && !value.is_power_of_two()) || value == 1 {
+                return None;
+            }
+            packed = packed << 4 | power;
+        }
+        Some(packed)
+    }
+
+    /// The tiles of a board of `size` from `pack`, on an otherwise new board;
+    /// None when the size has more than 16 cells
+    pub fn unpack(packed: u64, size: BoardSize, seed: u64) -> Option<Board> {
+        let cells = size.cols * size.rows;
+        if cells > 16 {
+            return None;
+        }
+        let mut board = Board::new(size, seed);
+        for (i, tile) in board.grid.iter_mut().flat_map(|column| column.iter_mut()).enumerate() {
+            // the first cell went in first, so it is furthest up
+            let power = (packed >> (4 * (cells - 1 - i))) & 0xf;
+            if power > 0 {
+                *tile = Tile::from_value(1 << power);
+            }
+        }
+        Some(board)
+    }
+
+    pub fn has_tile(&self, value: usize) -> bool {
+        for i in 0..self.size.cols {
+            for j in 0..self.size.rows {
+                // compare through Tile equality, get() may still show the old value
+                if self.grid[i][j] == Tile::from_value(value) {
+                    return true;
+                }
+            }
+        }
+
+        return false;
+    }
+
+    /// Puts a tile of `value` at (col, row), replacing what was there; 0 empties the cell
+    pub fn spawn_at(&mut self, (x, y): (usize, usize), value: usize) -> Result<(), String> {
+        if x >= self.size.cols || y >= self.size.rows {
+            return Err(format!("({}, {}) is outside a {}x{} board", x, y, self.size.cols, self.size.rows));
+        }
+        if value == 1 || (value != 0 && !value.is_power_of_two()) {
+            return Err(format!("invalid tile value: {}", value));
+        }
+        if value != 0 && self.is_wall(x, y) {
+            return Err(format!("({}, {}) is a wall", x, y));
+        }
+        self.grid[x][y] = Tile::from_value(value);
+        Ok(())
+    }
+
+    /// Blocks a cell, clearing any tile in it
+    pub fn add_wall(&mut self, (x, y): (usize, usize)) -> Result<(), String> {
+        if x >= self.size.cols || y >= self.size.rows {
+            return Err(format!("({}, {}) is outside a {}x{} board", x, y, self.size.cols, self.size.rows));
+        }
+        if !self.is_wall(x, y) {
+            self.grid[x][y] = Tile::new();
+            self.walls.push(Point { x: x, y: y });
+        }
+        Ok(())
+    }
+
+    /// The largest tile on the board, 0 when it is empty
+    pub fn max_tile(&self) -> usize {
+        self.grid.iter()
+            .flat_map(|column| column.iter())
+            .map(Tile::value)
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// Moves every tile, returning where each moved tile started and ended
+    pub fn move_all(&mut self, direc: Direction) -> Vec<Movement> {
+        self.merges = 0;
+        self.largest_merge = 0;
+
+        // each line runs from the edge the tiles move towards
+        let lines: Vec<Vec<Point>> = match direc {
+            Direction::Left | Direction::Right => (0..self.size.rows)
+                .map(|y| (0..self.size.cols).map(|x| Point { x: x, y: y }).collect())
+                .collect(),
+            Direction::Up | Direction::Down => (0..self.size.cols)
+                .map(|x| (0..self.size.rows).map(|y| Point { x: x, y: y }).collect())
+                .collect(),
+            _ => self.diagonal_lines(direc),
+        };
+
+        let mut movements = Vec::new();
+        for mut line_points in lines {
+            if direc == Direction::Right || direc == Direction::Down {
+                line_points.reverse();
+            }
+            // walls split a line into parts that slide on their own
+            let parts: Vec<Vec<Point>> = line_points
+                .split(|p| self.is_wall(p.x, p.y))
+                .filter(|part| !part.is_empty())
+                .map(|part| part.to_vec())
+                .collect();
+            for points in parts {
+                let before: Vec<usize> = points.iter().map(|p| self.grid[p.x][p.y].value()).collect();
+                let mut line: Vec<Tile> = before.iter().map(|&value| Tile::from_value(value)).collect();
+                // scored by the rule in count_merge instead
+                let mut classic = 0;
+                let mut moves = slide_line(&mut line, &mut classic);
+                // there's no seam to merge across when a wall is in the way
+                if self.wrap && points.len() == line_points.len() {
+                    merge_across_seam(&mut line, &mut moves);
+                }
+
+                for (i, p) in points.iter().enumerate() {
+                    // until the animation is done a cell shows the tile that stayed in it
+                    let stayed = if moves.iter().any(|&(from, _, _)| from == i) { 0 } else { before[i] };
+                    let mut tile = Tile::from_value(stayed);
+                    tile.set(line[i].value());
+                    tile.set_pending(moves.iter().any(|&(_, to, _)| to == i));
+                    self.grid[p.x][p.y] = tile;
+                }
+                for &(from, to, merged) in &moves {
+                    if merged {
+                        self.count_merge(line[to].value());
+                    }
+                    movements.push(Movement {
+                        tile: Tile::from_value(before[from]),
+                        pold: points[from],
+                        pnew: points[to],
+                        merged: merged,
+                    });
+                }
+                if !moves.is_empty() {
+                    self.moved = true;
+                }
+            }
+        }
+        if self.gravity {
+            self.settle(&mut movements);
+        }
+        movements
+    }
+
+    /// The diagonals of the grid that run in `direc`, each from the cell the
+    /// tiles move towards, down to a single cell in the corners
+    fn diagonal_lines(&self, direc: Direction) -> Vec<Vec<Point>> {
+        let (dx, dy) = direc.offset();
+        // each line is walked back against the move
+        let (back_x, back_y) = direc.opposite().offset();
+        let (cols, rows) = (self.size.cols as i32, self.size.rows as i32);
+        let inside = |x: i32, y: i32| x >= 0 && x < cols && y >= 0 && y < rows;
+        let mut lines = Vec::new();
+        for x in 0..cols {
+            for y in 0..rows {
+                // a line starts where there is no cell further along
+                if inside(x + dx, y + dy) {
+                    continue;
+                }
+                let mut line = Vec::new();
+                let (mut cx, mut cy) = (x, y);
+                while inside(cx, cy) {
+                    line.push(Point { x: cx as usize, y: cy as usize });
+                    cx += back_x;
+                    cy += back_y;
+                }
+                lines.push(line);
+            }
+        }
+        lines
+    }
+
+    /// Lets every tile fall to the bottom of its column after a move, whatever
+    /// its direction, and points the movements at where the tiles end up
+    ///
+    /// Falling tiles don't merge, so the score is the same as without gravity.
+    /// Cells they land in stay empty until the animation is done.
+    fn settle(&mut self, movements: &mut Vec<Movement>) {
+        for x in 0..self.size.cols {
+            // the lowest cell not yet taken
+            let mut floor = self.size.rows;
+            for y in (0..self.size.rows).rev() {
+                if self.is_wall(x, y) {
+                    // tiles above a wall come to rest on it
+                    floor = y;
+                    continue;
+                }
+                let tile = self.grid[x][y];
+                if tile.is_empty() {
+                    continue;
+                }
+                floor -= 1;
+                if floor == y {
+                    continue;
+                }
+                if tile.is_pending() {
+                    // it was still arriving, so its movements carry on down
+                    for m in movements.iter_mut().filter(|m| m.pnew.x == x && m.pnew.y == y) {
+                        m.pnew.y = floor;
+                    }
+                }
+                // what the cell shows until then falls too, such as a tile being merged into
+                if tile.get() != 0 {
+                    movements.push(Movement {
+                        tile: Tile::from_value(tile.get()),
+                        pold: Point { x: x, y: y },
+                        pnew: Point { x: x, y: floor },
+                        merged: false,
+                    });
+                }
+                self.grid[x][y] = Tile::new();
+                let mut landed = Tile::new();
+                landed.set(tile.value());
+                landed.set_pending(true);
+                self.grid[x][floor] = landed;
+                self.moved = true;
+            }
+        }
+    }
+}
+
+/// Slides the tiles of a line towards index 0, merging equal neighbours, and
+/// adds the merged values to `score`.
+///
+/// Returns where each tile that moved came from and went to, and whether it
+/// merged there. A tile formed by a merge doesn't merge again in the same move,
+/// so [2, 2, 4, 0] becomes [4, 4, 0, 0].
+pub fn slide_line(line: &mut Vec<Tile>, score: &mut usize) -> Vec<(usize, usize, bool)> {
+    let mut moves = Vec::new();
+    let mut slid: Vec<Tile> = Vec::with_capacity(line.len());
+    // whether the last tile placed may still take a merge
+    let mut open = false;
+    for (from, tile) in line.iter().enumerate() {
+        if tile.is_empty() {
+            continue;
+        }
+        let value = tile.value();
+        if open && slid.last().map(Tile::value) == Some(value) {
+            let to = slid.len() - 1;
+            slid[to] = Tile::from_value(value * 2);
+            *score += value * 2;
+            open = false;
+            moves.push((from, to, true));
+        } else {
+            let to = slid.len();
+            slid.push(Tile::from_value(value));
+            open = true;
+            if from != to {
+                moves.push((from, to, false));
+            }
+        }
+    }
+    slid.resize(line.len(), Tile::new());
+    *line = slid;
+    moves
+}
+
+/// Lets the tile at the far end of a line slide off the edge and come back
+/// round onto the tile at the near end, merging if they are equal, for a board
+/// whose opposite edges are joined
+///
+/// Takes a line and the moves `slide_line` made in it. Neither tile may have
+/// merged already, so at most one merge a move crosses the seam:
+/// [2, 4, 2, 0] becomes [4, 4, 0, 0]. Returns whether they merged.
+pub fn merge_across_seam(line: &mut Vec<Tile>, moves: &mut Vec<(usize, usize, bool)>) -> bool {
+    let last = match line.iter().rposition(|tile| !tile.is_empty()) {
+        Some(last) if last > 0 => last,
+        _ => return false,
+    };
+    let merged_into = |to| moves.iter().any(|&(_, t, merged)| merged && t == to);
+    if line[0].value() != line[last].value() || merged_into(0) || merged_into(last) {
+        return false;
+    }
+
+    // the far tile goes on from wherever it started
+    let from = moves.iter()
+        .find(|&&(_, to, _)| to == last)
+        .map_or(last, |&(from, _, _)| from);
+    moves.retain(|&(_, to, _)| to != last);
+    moves.push((from, 0, true));
+    line[0] = Tile::from_value(line[0].value() * 2);
+    line[last] = Tile::new();
+    true
+}
+
+impl PartialEq for Board {
+    /// Boards are equal when they show the same position, whatever their RNG state
+    fn eq(&self, other: &Board) -> bool {
+        self.size == other.size
+            && self.grid == other.grid
+            && self.score == other.score
+            && self.state == other.state
+            && self.walls == other.walls
+    }
+}
+
+/// The parts of a board kept in its JSON form
+#[derive(Serialize, Deserialize)]
+struct BoardJson {
+    cols: usize,
+    rows: usize,
+    /// Tile values, one array per row
+    grid: Vec<Vec<usize>>,
+    score: usize,
+    /// Part of the score given at the start
+    #[serde(default, skip_serializing_if = "is_zero")]
+    start_score: usize,
+    state: State,
+    /// Wall cells as (column, row)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    walls: Vec<(usize, usize)>,
+}
+
+fn is_zero(n: &usize) -> bool {
+    *n == 0
+}
+
+impl Board {
+    pub fn to_json(&self) -> String {
+        let json = BoardJson {
+            cols: self.size.cols,
+            rows: self.size.rows,
+            grid: (0..self.size.rows)
+                .map(|y| (0..self.size.cols).map(|x| self.grid[x][y].value()).collect())
+                .collect(),
+            score: self.score,
+            start_score: self.start_score,
+            state: self.state,
+            walls: self.walls.iter().map(|wall| (wall.x, wall.y)).collect(),
+        };
+        serde_json::to_string(&json).expect("a board always serializes")
+    }
+
+    /// Reads a board written by `to_json`, new tiles come from a random seed
+    pub fn from_json(text: &str) -> Result<Board, String> {
+        let json: BoardJson = serde_json::from_str(text).map_err(|e| e.to_string())?;
+        let size = BoardSize { cols: json.cols, rows: json.rows };
+        if json.grid.len() != size.rows || json.grid.iter().any(|row| row.len() != size.cols) {
+            return Err(format!("grid does not match a {}x{} board", size.cols, size.rows));
+        }
+
+        let mut board = Board::new(size, rand::random());
+        for (y, row) in json.grid.iter().enumerate() {
+            for (x, &value) in row.iter().enumerate() {
+                board.spawn_at((x, y), value)?;
+            }
+        }
+        for &(x, y) in &json.walls {
+            board.add_wall((x, y))?;
+        }
+        board.score = json.score;
+        board.start_score = json.start_score;
+        board.state = json.state;
+        Ok(board)
+    }
+
+    /// Reads a starting position of the given size, either JSON from `to_json`
+    /// or rows of tile values separated by spaces or commas, 0 for an empty cell
+    /// and # for a wall, with an optional `score: N` line
+    pub fn from_text(text: &str, size: BoardSize, seed: u64) -> Result<Board, String> {
+        let mut board = Board::new(size, seed);
+        if text.trim().starts_with('{') {
+            let loaded = Board::from_json(text)?;
+            if loaded.size != size {
+                return Err(format!("the board is {}x{}, not {}x{}",
+                                   loaded.size.cols, loaded.size.rows, size.cols, size.rows));
+            }
+            board.grid = loaded.grid;
+            board.walls = loaded.walls;
+            board.score = loaded.score;
+            board.start_score = loaded.start_score;
+            board.state = loaded.state;
+            return Ok(board);
+        }
+
+        let mut rows = 0;
+        for line in text.lines().map(str::trim).filter(|line| !line.is_empty()) {
+            if line.starts_with("score:") {
+                let score = line["score:".len()..].trim();
+                board.score = score.parse().map_err(|_| format!("invalid score: {}", score))?;
+                continue;
+            }
+            if rows == size.rows {
+                return Err(format!("more than {} rows", size.rows));
+            }
+            let values: Vec<&str> = line.split(|c: char| c == ',' || c.is_whitespace())
+                .filter(|value| !value.is_empty())
+                .collect();
+            if values.len() != size.cols {
+                return Err(format!("row {} has {} tiles, not {}", rows + 1, values.len(), size.cols));
+            }
+            for (x, &value) in values.iter().enumerate() {
+                if value == "#" {
+                    board.add_wall((x, rows))?;
+                    continue;
+                }
+                let value = value.parse().map_err(|_| format!("invalid tile value: {}", value))?;
+                board.spawn_at((x, rows), value)?;
+            }
+            rows += 1;
+        }
+        if rows != size.rows {
+            return Err(format!("{} rows, not {}", rows, size.rows));
+        }
+        Ok(board)
+    }
+}
+
+impl fmt::Display for Board {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for y in 0..self.size.rows {
+            for x in 0..self.size.cols {
+                if x > 0 {
+                    write!(f, " ")?;
+                }
+                write!(f, "{:>5}", self.grid[x][y].value())?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn line(values: &[usize]) -> Vec<Tile> {
+        values.iter().map(|&value| Tile::from_value(value)).collect()
+    }
+
+    fn values(line: &[Tile]) -> Vec<usize> {
+        line.iter().map(Tile::value).collect()
+    }
+
+    /// Slides a line, returning where it ends up and the points it scored
+    fn slid(before: &[usize]) -> (Vec<usize>, usize) {
+        let mut tiles = line(before);
+        let mut score = 0;
+        slide_line(&mut tiles, &mut score);
+        (values(&tiles), score)
+    }
+
+    fn board(text: &str, cols: usize, rows: usize) -> Board {
+        Board::from_text(text, BoardSize { cols: cols, rows: rows }, 1).unwrap()
+    }
+
+    /// Tile values row by row, as `from_text` reads them
+    fn grid_rows(board: &Board) -> Vec<Vec<usize>> {
+        (0..board.size.rows).map(|y| (0..board.size.cols).map(|x| board.grid[x][y].value()).collect()).collect()
+    }
+
+    fn moved(text: &str, cols: usize, rows: usize, direc: Direction) -> Vec<Vec<usize>> {
+        let mut board = board(text, cols, rows);
+        board.move_all(direc);
+        grid_rows(&board)
+    }
+
+    #[test]
+    fn slide_line_edge_cases() {
+        assert_eq!(slid(&[2, 2, 2, 2]), (vec![4, 4, 0, 0], 8));
+        assert_eq!(slid(&[2, 2, 4, 0]), (vec![4, 4, 0, 0], 4));
+        assert_eq!(slid(&[4, 0, 4, 8]), (vec![8, 8, 0, 0], 8));
+        assert_eq!(slid(&[0, 0, 0, 2]), (vec![2, 0, 0, 0], 0));
+        assert_eq!(slid(&[2, 4, 8, 16]), (vec![2, 4, 8, 16], 0));
+        assert_eq!(slid(&[0, 0, 0, 0]), (vec![0, 0, 0, 0], 0));
+    }
+
+    #[test]
+    fn last_column_of_a_wide_board_can_move() {
+        // full, and the only merge is down the fifth column
+        let stuck_but_for = board("2 4 2 4 8\n4 2 4 2 8\n2 4 2 4 2\n4 2 4 2 4", 5, 4);
+        assert!(stuck_but_for.can_move());
+        let stuck = board("2 4 2 4 8\n4 2 4 2 16\n2 4 2 4 2\n4 2 4 2 4", 5, 4);
+        assert!(!stuck.can_move());
+    }
+
+    #[test]
+    fn merges_start_from_the_edge_moved_to() {
+        assert_eq!(moved("2 2 2 2", 4, 1, Direction::Right), vec![vec![0, 0, 4, 4]]);
+        assert_eq!(moved("2 2 2 0", 4, 1, Direction::Right), vec![vec![0, 0, 2, 4]]);
+        assert_eq!(moved("2 2 2 0", 4, 1, Direction::Left), vec![vec![4, 2, 0, 0]]);
+        assert_eq!(moved("4 4 8 0", 4, 1, Direction::Right), vec![vec![0, 0, 8, 8]]);
+        assert_eq!(moved("2\n2\n2\n0", 1, 4, Direction::Down), vec![vec![0], vec![0], vec![2], vec![4]]);
+        assert_eq!(moved("2\n2\n2\n0", 1, 4, Direction::Up), vec![vec![4], vec![2], vec![0], vec![0]]);
+    }
+
+    #[test]
+    fn json_round_trip() {
+        let mut original = board("2 0 4\n# 8 0", 3, 2);
+        original.score = 36;
+        original.start_score = 20;
+        original.state = State::Won;
+        let loaded = Board::from_json(&original.to_json()).unwrap();
+        assert!(loaded == original);
+        assert_eq!(loaded.size, BoardSize { cols: 3, rows: 2 });
+        assert!(loaded.is_wall(0, 1));
+        assert_eq!(loaded.start_score, 20);
+        assert!(Board::from_json("{\"cols\":2,\"rows\":1,\"grid\":[[2]],\"score\":0,\"state\":\"Playing\"}").is_err());
+    }
+
+    #[test]
+    fn pending_tiles_merge_by_their_new_value() {
+        let mut pending = Tile::from_value(2);
+        pending.set(4);
+        pending.set_pending(true);
+        assert_eq!(pending.get(), 2);
+        assert!(pending == Tile::from_value(4));
+        assert!(pending != Tile::from_value(2));
+
+        // the second move comes before the first is done animating
+        let mut board = board("2 2 4 0", 4, 1);
+        board.move_all(Direction::Left);
+        assert!(board.grid[0][0].is_pending());
+        board.move_all(Direction::Left);
+        assert_eq!(grid_rows(&board), vec![vec![8, 0, 0, 0]]);
+        assert_eq!(board.score, 12);
+    }
+
+    #[test]
+    fn no_tile_merges_twice_in_a_move() {
+        assert_eq!(slid(&[2, 2, 2]), (vec![4, 2, 0], 4));
+        assert_eq!(slid(&[4, 4, 2, 2]), (vec![8, 4, 0, 0], 12));
+        assert_eq!(slid(&[4, 2, 2]), (vec![4, 4, 0], 4));
+        assert_eq!(slid(&[8, 4, 2, 2]), (vec![8, 4, 4, 0], 4));
+        let mut tiles = line(&[2, 2, 4, 4]);
+        let moves = slide_line(&mut tiles, &mut 0);
+        assert_eq!(values(&tiles), vec![4, 8, 0, 0]);
+        // each target is merged into once
+        assert_eq!(moves, vec![(1, 0, true), (2, 1, false), (3, 1, true)]);
+    }
+
+    #[test]
+    fn available_moves_on_full_boards() {
+        // full, but the top row can still merge sideways
+        let full = board("2 2 4 8\n4 8 16 32\n8 16 32 64\n16 32 64 128", 4, 4);
+        assert_eq!(full.available_moves(), vec![Direction::Left, Direction::Right]);
+        let stuck = board("2 4 2 4\n4 2 4 2\n2 4 2 4\n4 2 4 2", 4, 4);
+        assert!(stuck.available_moves().is_empty());
+        assert!(stuck.is_lost());
+        let open = board("2 0 0 0\n0 0 0 0\n0 0 0 0\n0 0 0 0", 4, 4);
+        assert_eq!(open.available_moves(), vec![Direction::Down, Direction::Right]);
+    }
+
+    #[test]
+    fn clone_and_move_leaves_the_board_alone() {
+        let original = board("2 2 0 4\n0 0 0 0\n4 0 0 4\n0 0 0 0", 4, 4);
+        let before = original.clone();
+        let (moved, changed) = original.clone_and_move(Direction::Left);
+        assert!(changed);
+        assert_eq!(grid_rows(&moved)[0], vec![4, 4, 0, 0]);
+        assert_eq!(grid_rows(&moved)[2], vec![8, 0, 0, 0]);
+        assert_eq!(moved.score, 12);
+        assert!(original == before);
+        assert!(!original.moved);
+        assert_eq!(original.merges, 0);
+        assert_eq!(original.spawn_roll, before.spawn_roll);
+
+        let (same, changed) = board("2 0 0 0", 4, 1).clone_and_move(Direction::Left);
+        assert!(!changed);
+        assert_eq!(grid_rows(&same), vec![vec![2, 0, 0, 0]]);
+    }
+
+    #[test]
+    fn add_tile_finds_the_last_free_cell() {
+        for seed in 0..20 {
+            let mut full = Board::from_text("2 4 2 4 2\n4 2 4 2 4\n2 4 0 4 2\n4 2 4 2 4",
+                                            BoardSize { cols: 5, rows: 4 }, seed).unwrap();
+            let new = full.add_tile().unwrap();
+            assert!(new.position == Point { x: 2, y: 2 });
+            assert!(new.value == 2 || new.value == 4);
+            full.spawn_at((2, 2), new.value).unwrap();
+            assert!(full.add_tile().is_none());
+        }
+    }
+
+    fn with_gravity(text: &str, direc: Direction) -> Board {
+        let mut board = board(text, 4, 4);
+        board.gravity = true;
+        board.move_all(direc);
+        board
+    }
+
+    #[test]
+    fn gravity_settles_every_move() {
+        let board = with_gravity("0 2 0 0\n0 0 0 0\n0 0 4 0\n0 0 0 0", Direction::Left);
+        assert_eq!(grid_rows(&board), vec![vec![0, 0, 0, 0], vec![0, 0, 0, 0], vec![2, 0, 0, 0], vec![4, 0, 0, 0]]);
+        assert!(board.moved);
+        // falling onto an equal tile doesn't merge with it
+        let board = with_gravity("2 0 0 0\n0 0 0 0\n0 0 0 0\n2 0 0 4", Direction::Right);
+        assert_eq!(grid_rows(&board), vec![vec![0, 0, 0, 0], vec![0, 0, 0, 0], vec![0, 0, 0, 2], vec![0, 0, 2, 4]]);
+        assert_eq!(board.score, 0);
+    }
+
+    #[test]
+    fn gravity_stops_on_walls() {
+        let board = with_gravity("0 2 0 0\n0 0 0 0\n# 4 0 0\n0 0 0 0", Direction::Left);
+        assert_eq!(grid_rows(&board), vec![vec![0, 0, 0, 0], vec![2, 0, 0, 0], vec![0, 0, 0, 0], vec![0, 4, 0, 0]]);
+        assert!(board.is_wall(0, 2));
+    }
+
+    /// Slides a line on a board whose edges are joined
+    fn wrapped(before: &[usize]) -> Vec<usize> {
+        let mut tiles = line(before);
+        let mut moves = slide_line(&mut tiles, &mut 0);
+        merge_across_seam(&mut tiles, &mut moves);
+        values(&tiles)
+    }
+
+    #[test]
+    fn merges_across_the_seam() {
+        assert_eq!(wrapped(&[2, 4, 2, 0]), vec![4, 4, 0, 0]);
+        assert_eq!(wrapped(&[2, 4, 8, 2]), vec![4, 4, 8, 0]);
+        assert_eq!(wrapped(&[4, 2, 2, 4]), vec![8, 4, 0, 0]);
+        // tiles that merged already stay put
+        assert_eq!(wrapped(&[2, 2, 4, 4]), vec![4, 8, 0, 0]);
+        assert_eq!(wrapped(&[2, 2, 4, 2]), vec![4, 4, 2, 0]);
+        assert_eq!(wrapped(&[2, 4, 2, 4]), vec![2, 4, 2, 4]);
+        assert_eq!(wrapped(&[2, 0, 0, 0]), vec![2, 0, 0, 0]);
+    }
+
+    #[test]
+    fn one_seam_merge_a_move() {
+        // 2 4 2 then 4 4 would merge twice if the seam merge ran again
+        let mut tiles = line(&[2, 4, 2, 0]);
+        let mut moves = slide_line(&mut tiles, &mut 0);
+        assert!(merge_across_seam(&mut tiles, &mut moves));
+        assert!(!merge_across_seam(&mut tiles, &mut moves));
+        assert_eq!(values(&tiles), vec![4, 4, 0, 0]);
+        assert_eq!(moves, vec![(2, 0, true)]);
+
+        let mut board = board("2 4 2 0\n2 # 4 2", 4, 2);
+        board.wrap = true;
+        board.move_all(Direction::Left);
+        // a wall in the row leaves no seam to cross
+        assert_eq!(grid_rows(&board), vec![vec![4, 4, 0, 0], vec![2, 0, 4, 2]]);
+        assert_eq!(board.score, 4);
+    }
+
+    #[test]
+    fn scoring_rules_score_the_same_merges() {
+        let scored = |rule| {
+            let mut board = board("2 2 4 4\n8 8 0 0\n0 0 0 0\n0 0 0 0", 4, 4);
+            board.scoring = rule;
+            board.move_all(Direction::Left);
+            board.score
+        };
+        // merges into 4, 8 and 16
+        assert_eq!(scored(ScoringRule::Classic), 28);
+        assert_eq!(scored(ScoringRule::MergeCount), 3);
+        assert_eq!(scored(ScoringRule::Log2), 2 + 3 + 4);
+    }
+
+    /// Where new tiles land on an empty 4x3 board under `policy`, over many seeds
+    fn spawn_cells(policy: SpawnPolicy) -> Vec<(usize, usize)> {
+        let mut cells = Vec::new();
+        for seed in 0..200 {
+            let mut board = Board::new(BoardSize { cols: 4, rows: 3 }, seed);
+            board.spawn = policy;
+            let new = board.add_tile().unwrap();
+            if !cells.contains(&(new.position.x, new.position.y)) {
+                cells.push((new.position.x, new.position.y));
+            }
+        }
+        cells.sort();
+        cells
+    }
+
+    #[test]
+    fn spawns_follow_the_policy() {
+        assert_eq!(spawn_cells(SpawnPolicy::CornerOnly), vec![(0, 0), (0, 2), (3, 0), (3, 2)]);
+        assert_eq!(spawn_cells(SpawnPolicy::EdgeOnly(Direction::Up)), vec![(0, 0), (1, 0), (2, 0), (3, 0)]);
+        assert_eq!(spawn_cells(SpawnPolicy::EdgeOnly(Direction::Right)), vec![(3, 0), (3, 1), (3, 2)]);
+        assert_eq!(spawn_cells(SpawnPolicy::EdgeOnly(Direction::DownLeft)),
+                   vec![(0, 0), (0, 1), (0, 2), (1, 2), (2, 2), (3, 2)]);
+        assert_eq!(spawn_cells(SpawnPolicy::Uniform).len(), 12);
+    }
+
+    fn points(line: &[Point]) -> Vec<(usize, usize)> {
+        line.iter().map(|p| (p.x, p.y)).collect()
+    }
+
+    #[test]
+    fn diagonal_lines_of_non_square_boards() {
+        let wide = Board::new(BoardSize { cols: 3, rows: 2 }, 1);
+        let lines: Vec<Vec<(usize, usize)>> = wide.diagonal_lines(Direction::DownRight)
+            .iter()
+            .map(|line| points(line))
+            .collect();
+        // each from the cell the tiles move towards
+        assert_eq!(lines, vec![vec![(0, 1)], vec![(1, 1), (0, 0)], vec![(2, 0)], vec![(2, 1), (1, 0)]]);
+
+        let tall = Board::new(BoardSize { cols: 2, rows: 3 }, 1);
+        let lines = tall.diagonal_lines(Direction::UpLeft);
+        let mut cells: Vec<(usize, usize)> = lines.iter().flat_map(|line| points(line)).collect();
+        cells.sort();
+        // every cell once, however the lines are cut
+        assert_eq!(cells, vec![(0, 0), (0, 1), (0, 2), (1, 0), (1, 1), (1, 2)]);
+        assert_eq!(lines.len(), 4);
+        for line in &lines {
+            assert!(line.len() <= 2);
+            for pair in line.windows(2) {
+                assert!(pair[1].x == pair[0].x + 1 && pair[1].y == pair[0].y + 1);
+            }
+        }
+    }
+
+    #[test]
+    fn spawns_go_anywhere_once_the_policy_is_full() {
+        for seed in 0..20 {
+            // the corners and the top edge taken, the middle of the bottom free
+            let mut board = Board::from_text("2 4 8 2\n0 0 0 4\n8 0 0 16", BoardSize { cols: 4, rows: 3 }, seed)
+                .unwrap();
+            for &policy in &[SpawnPolicy::CornerOnly, SpawnPolicy::EdgeOnly(Direction::Up)] {
+                board.spawn = policy;
+                let new = board.add_tile().unwrap();
+                assert!(!policy.allows(new.position, board.size));
+                assert!(board.grid[new.position.x][new.position.y].is_empty());
+            }
+        }
+    }
+
+    #[test]
+    fn diagonal_moves_slide_and_merge() {
+        let mut square = board("2 0 0\n0 2 0\n0 0 4", 3, 3);
+        square.diagonals = true;
+        assert_eq!(square.available_moves().len(), 8);
+        square.move_all(Direction::DownRight);
+        assert_eq!(grid_rows(&square), vec![vec![0, 0, 0], vec![0, 4, 0], vec![0, 0, 4]]);
+        assert_eq!(square.score, 4);
+        square.move_all(Direction::DownRight);
+        assert_eq!(grid_rows(&square), vec![vec![0, 0, 0], vec![0, 0, 0], vec![0, 0, 8]]);
+
+        let mut across = board("0 0 2\n0 0 0\n2 0 0", 3, 3);
+        across.diagonals = true;
+        across.move_all(Direction::UpRight);
+        assert_eq!(grid_rows(&across), vec![vec![0, 0, 4], vec![0, 0, 0], vec![0, 0, 0]]);
+    }
+
+    #[test]
+    fn pack_and_unpack() {
+        let original = board("2 0 4 32768\n0 0 0 8\n1024 0 0 0\n0 2048 0 2", 4, 4);
+        let packed = original.pack().unwrap();
+        // the first column, top down, fills the highest bits
+        assert_eq!(packed >> 48, 0x10a0);
+        let unpacked = Board::unpack(packed, original.size, 1).unwrap();
+        assert!(unpacked.grid == original.grid);
+        assert_eq!(Board::new(BoardSize::default(), 1).pack(), Some(0));
+
+        let small = board("2 4 8", 3, 1);
+        assert_eq!(small.pack(), Some(0x123));
+        assert!(Board::unpack(0x123, small.size, 1).unwrap().grid == small.grid);
+    }
+
+    #[test]
+    fn pack_refuses_what_does_not_fit() {
+        // 17 cells and more
+        assert_eq!(Board::new(BoardSize { cols: 5, rows: 4 }, 1).pack(), None);
+        assert!(Board::unpack(0, BoardSize { cols: 5, rows: 4 }, 1).is_none());
+        assert!(Board::new(BoardSize { cols: 2, rows: 8 }, 1).pack().is_some());
+        // past 2^15
+        let mut big = Board::new(BoardSize::default(), 1);
+        big.spawn_at((0, 0), 65536).unwrap();
+        assert_eq!(big.pack(), None);
+        big.spawn_at((0, 0), 32768).unwrap();
+        assert!(big.pack().is_some());
+    }
+
+    #[test]
+    fn walls_split_rows() {
+        let mut board = board("0 2 # 0 2\n2 2 # 2 2", 5, 2);
+        board.move_all(Direction::Left);
+        assert_eq!(grid_rows(&board), vec![vec![2, 0, 0, 2, 0], vec![4, 0, 0, 4, 0]]);
+        board.move_all(Direction::Right);
+        assert_eq!(grid_rows(&board), vec![vec![0, 2, 0, 0, 2], vec![0, 4, 0, 0, 4]]);
+        // nothing merges across the wall
+        assert_eq!(board.score, 8);
+        assert!(board.is_wall(2, 0) && board.is_wall(2, 1));
+    }
+
+    #[test]
+    fn all_and_opposite() {
+        let all = Direction::all();
+        assert_eq!(all.len(), 4);
+        for direc in all.iter().chain(DIAGONALS.iter()) {
+            let (dx, dy) = direc.offset();
+            assert_eq!(direc.opposite().offset(), (-dx, -dy));
+            assert_eq!(direc.opposite().opposite(), *direc);
+            assert_eq!(all.contains(&direc.opposite()), all.contains(direc));
+        }
+        assert_eq!(Direction::Left.opposite(), Direction::Right);
+        assert_eq!(Direction::UpRight.opposite(), Direction::DownLeft);
+    }
+}
@@ -0,0 +1,47 @@
+//! Seeds for the daily challenge
+
+/// The UTC date `secs` seconds after the Unix epoch, as year, month and day
+pub fn utc_date(secs: u64) -> (u64, u32, u32) {
+    // civil_from_days from http://howardhinnant.github.io/date_algorithms.html
+    let z = secs / 86400 + 719468;
+    let era = z / 146097;
+    let doe = z % 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+/// Seed of the daily challenge on a date, the same for everyone that day
+pub fn daily_seed(year: u64, month: u32, day: u32) -> u64 {
+    // YYYYMMDD mixed by the splitmix64 finaliser so nearby days differ widely
+    let mut z = year * 10000 + month as u64 * 100 + day as u64;
+    z = z.wrapping_add(0x9e3779b97f4a7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+    z ^ (z >> 31)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dates_from_seconds() {
+        assert_eq!(utc_date(0), (1970, 1, 1));
+        assert_eq!(utc_date(951_782_400), (2000, 2, 29));
+        assert_eq!(utc_date(1_709_164_800 - 1), (2024, 2, 28));
+        assert_eq!(utc_date(1_709_164_800), (2024, 2, 29));
+        assert_eq!(utc_date(1_735_689_599), (2024, 12, 31));
+    }
+
+    #[test]
+    fn seed_of_a_date() {
+        // a change here gives everyone a different challenge than before
+        assert_eq!(daily_seed(2024, 2, 29), 2971461144707196042);
+        assert!(daily_seed(2024, 2, 29) != daily_seed(2024, 3, 1));
+    }
+}
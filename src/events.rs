@@ -0,0 +1,59 @@
+//! Notifications of what happens in a game, for programs following along
+
+use std::io::Write;
+
+use serde_json;
+
+use board::{Direction, State};
+
+/// Receives what happens in a game as it happens
+pub trait EventSink {
+    /// A move changed the board and scored `gained` points
+    fn on_move(&mut self, direc: Direction, gained: usize);
+    /// A new tile appeared at (col, row)
+    fn on_spawn(&mut self, pos: (usize, usize), value: usize);
+    /// The game was won or lost, or went back to being played
+    fn on_state(&mut self, state: State);
+}
+
+/// One event as written by `JsonLinesSink`
+#[derive(Serialize)]
+#[serde(tag = "event", rename_all = "lowercase")]
+enum Event {
+    Move { direction: &'static str, gained: usize },
+    Spawn { col: usize, row: usize, value: usize },
+    State { state: State },
+}
+
+/// Writes every event as a JSON object on a line of its own, such as
+/// `{"event":"move","direction":"left","gained":4}`
+pub struct JsonLinesSink<W: Write> {
+    out: W,
+}
+
+impl<W: Write> JsonLinesSink<W> {
+    pub fn new(out: W) -> JsonLinesSink<W> {
+        JsonLinesSink { out: out }
+    }
+
+    fn write(&mut self, event: Event) {
+        let line = serde_json::to_string(&event).expect("an event always serializes");
+        // a reader that went away doesn't stop the game
+        let _ = writeln!(self.out, "{}", line);
+        let _ = self.out.flush();
+    }
+}
+
+impl<W: Write> EventSink for JsonLinesSink<W> {
+    fn on_move(&mut self, direc: Direction, gained: usize) {
+        self.write(Event::Move { direction: direc.name(), gained: gained });
+    }
+
+    fn on_spawn(&mut self, (col, row): (usize, usize), value: usize) {
+        self.write(Event::Spawn { col: col, row: row, value: value });
+    }
+
+    fn on_state(&mut self, state: State) {
+        self.write(Event::State { state: state });
+    }
+}
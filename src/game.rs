@@ -0,0 +1,1740 @@
+//! A game in progress: the board plus history, timing, animation and scoring
+
+use std::cmp::Ordering;
+use std::collections::{HashSet, VecDeque};
+use std::env;
+use std::fs;
+use std::io::Write;
+use std::path::PathBuf;
+use std::time;
+
+use board::{Appearing, Board, Direction, Movement, Point, State, Tile, DIAGONALS};
+use events::EventSink;
+use goal::Goal;
+use profile::{profile_dir, DEFAULT_PROFILE};
+use record::key_name;
+use replay::Replay;
+use solver::{best_move, Strategy};
+use stats::{Outcome, StatsLog};
+use ui::{Key, UI};
+
+/// How many moves can be undone
+const HISTORY_LIMIT: usize = 16;
+/// Pause between moves made by the auto-solver in milliseconds
+const AUTO_DELAY_MS: u64 = 200;
+/// Animation frames drawn a second unless set otherwise
+pub const DEFAULT_FPS: u64 = 30;
+/// Part of the animation spent sliding, merged tiles pop and new tiles grow
+/// in during the rest
+const SLIDE_SHARE: f32 = 0.6;
+/// Size of a popping tile at its largest, relative to a cell
+const POP_SCALE: f32 = 1.4;
+/// Time between the bells of one pattern
+const BELL_GAP_MS: u64 = 150;
+/// Repeats of a held direction kept to play next, any more are dropped
+const HELD_REPEATS: usize = 2;
+/// Most keys read ahead at once, so endless input can't hold up a frame
+const READ_AHEAD_LIMIT: usize = 64;
+
+/// What a single call to `Game::step` did
+#[derive(Debug)]
+pub struct StepResult {
+    /// Whether any tile moved
+    pub moved: bool,
+    /// Points scored by the step
+    pub gained: usize,
+    pub state: State,
+}
+
+/// Final numbers of a game, see `UI::draw_summary`
+pub struct Summary {
+    pub state: State,
+    pub score: usize,
+    /// Part of `score` given at the start, see `Board::start_score`
+    pub start_score: usize,
+    pub moves: usize,
+    pub time: time::Duration,
+    pub max_tile: usize,
+    /// The score beat the best of previous runs
+    pub new_high_score: bool,
+    /// Percentage of moves the solver would have made too, with `Game::analyze`
+    pub optimal_percent: Option<usize>,
+    /// What each player scored in a hotseat match
+    pub player_scores: Option<[usize; 2]>,
+    /// The player who lost a hotseat match, None on a draw
+    pub loser: Option<usize>,
+}
+
+/// How a sliding tile's speed changes on its way, see `Easing::apply`
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Easing {
+    /// The same speed all the way
+    Linear,
+    /// Fast at first, slowing down into place
+    EaseOut,
+    /// Speeding up, then slowing down into place
+    EaseInOut,
+}
+
+impl Easing {
+    /// How far along a tile is when `t` of the slide's time has passed, both
+    /// from 0 to 1
+    pub fn apply(self, t: f32) -> f32 {
+        let t = t.max(0.0).min(1.0);
+        match self {
+            Easing::Linear => t,
+            Easing::EaseOut => 1.0 - (1.0 - t).powi(3),
+            Easing::EaseInOut if t < 0.5 => 4.0 * t.powi(3),
+            Easing::EaseInOut => 1.0 - (2.0 - 2.0 * t).powi(3) / 2.0,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Easing::Linear => "linear",
+            Easing::EaseOut => "ease-out",
+            Easing::EaseInOut => "ease-in-out",
+        }
+    }
+
+    /// Reads a name written by `name`
+    pub fn from_name(name: &str) -> Option<Easing> {
+        match name {
+            "linear" => Some(Easing::Linear),
+            "ease-out" => Some(Easing::EaseOut),
+            "ease-in-out" => Some(Easing::EaseInOut),
+            _ => None,
+        }
+    }
+}
+
+pub struct Game<'a> {
+    pub ui: &'a UI,
+    pub board: Board,
+    /// The player has won and chose to keep playing
+    pub continue_after_win: bool,
+    /// Boards before each of the latest moves, oldest first
+    pub history: Vec<Board>,
+    /// Best score of previous runs
+    pub high_score: usize,
+    /// Whose best score it is, see `set_profile`
+    pub profile: String,
+    /// The best score is read from the profile's files and written back when
+    /// beaten; off until `set_profile`, so library users and tests leave
+    /// the player's files alone
+    pub keep_high_score: bool,
+    /// Keys other than pause and quit are ignored while paused
+    pub paused: bool,
+    /// The help overlay is shown, the next key only dismisses it
+    pub help: bool,
+    /// Number of moves that changed the board
+    pub moves: usize,
+    /// Merges made by the latest move
+    pub last_combo: usize,
+    /// Largest tile formed by a merge this game
+    pub best_tile: usize,
+    /// Time played before `clock_start`
+    pub played: time::Duration,
+    /// When the clock last started, None while it is stopped
+    pub clock_start: Option<time::Instant>,
+    /// Vector containing tiles and their original position and destination
+    pub tiles_moving: Vec<Movement>,
+    /// where new tiles are appearing
+    pub points_appearing: Vec<Appearing>,
+    /// The time when the latest movement started
+    pub animation_start: time::Instant,
+    /// When the latest new tiles start growing in, after the slide that made room
+    pub spawn_start: time::Instant,
+    /// When drawing the latest frame started
+    pub frame_drawn: time::Instant,
+    /// Duration of the entire animation in milliseconds, 0 turns animation off
+    pub animation_ms: u64,
+    /// Most animation frames drawn a second, see `frame_ms`
+    pub fps: u64,
+    /// How tiles speed up and slow down as they slide
+    pub easing: Easing,
+    /// Plays instead of waiting for keys when set
+    pub strategy: Option<Box<Strategy>>,
+    /// Keys to play before waiting for the player, such as a replay
+    pub script: VecDeque<Key>,
+    /// The player paused the game or opened the help while the script plays,
+    /// which waits for them to close it, see `next_scripted`
+    pub held: bool,
+    /// Keys read ahead while a direction is held, played before any others
+    pub queued: VecDeque<Key>,
+    /// Where every applied key is written, see `Recording`
+    pub recorder: Option<fs::File>,
+    /// Say so when a move does nothing
+    pub hints: bool,
+    /// Shown until the next move that changes the board
+    pub hint: Option<String>,
+    /// Shown with the instructions, such as the date of a daily challenge
+    pub tag: Option<String>,
+    /// Shown instead of the usual keys, when others apply
+    pub instructions: Option<String>,
+    /// Shown once the game is over, until a key other than r or q dismisses it
+    pub summary: Option<Summary>,
+    /// Ring the bell on big merges, and when the game is won or lost
+    pub bell: bool,
+    /// Smallest merged tile that rings the bell
+    pub bell_merge: usize,
+    /// When the bells still to ring are due, earliest first
+    pub bells: VecDeque<time::Instant>,
+    /// Play time allowed in blitz mode
+    pub time_limit: Option<time::Duration>,
+    /// The game was lost to the time limit
+    pub time_up: bool,
+    /// Moves in other directions are refused, for drills
+    pub allowed_directions: HashSet<Direction>,
+    /// Draw coordinates and tile flags over the board
+    pub debug_coords: bool,
+    /// Told about moves, new tiles and changes of state
+    pub events: Option<Box<EventSink>>,
+    /// Tiles placed at the start of a game
+    pub start_tiles: usize,
+    /// Gets a line for every game that is reset or quit
+    pub stats: Option<StatsLog>,
+    /// Wins the game when reached, on top of the win target
+    pub goal: Option<Box<Goal>>,
+    /// The game is never lost, a stuck board can have its smallest tiles cleared
+    pub zen: bool,
+    /// Show the value of the next new tile with the stats
+    pub show_next: bool,
+    /// Time in milliseconds during which the same direction again is ignored, 0 for never
+    pub debounce_ms: u64,
+    /// Direction and time of the latest move that changed the board
+    pub last_move: Option<(Direction, time::Instant)>,
+    /// Undos allowed in each game, None for as many as the history holds
+    pub undo_limit: Option<usize>,
+    /// Undos left in this game when they are limited
+    pub undos_remaining: Option<usize>,
+    /// Shuffles allowed in each game, 0 turns the m key off
+    pub shuffle_limit: usize,
+    /// Shuffles left in this game
+    pub shuffles_left: usize,
+    /// Tiles the player may remove in each game, 0 turns the e key off
+    pub removal_limit: usize,
+    /// Removals left in this game
+    pub removals_left: usize,
+    /// The cell picked for removal, while one is being picked
+    pub cursor: Option<Point>,
+    /// Compare every move with the solver's, which takes time on each move
+    pub analyze: bool,
+    /// = (Tab in the terminal) shows what each move would leave, see `preview_board`
+    pub preview: bool,
+    /// The move being previewed, until a key other than = is pressed
+    pub previewing: Option<Direction>,
+    /// Moves this game that matched the solver's choice
+    pub optimal_moves: usize,
+    /// Two players take turns on the board, each scoring their own merges
+    pub hotseat: bool,
+    /// Whose turn it is in a hotseat match, 0 or 1
+    pub current_player: usize,
+    /// What each player's merges scored in a hotseat match
+    pub player_scores: [usize; 2],
+    /// The board before the start tiles of this game, for watching it again
+    pub start_board: Option<Board>,
+    /// Every key applied this game, in order
+    pub keys_played: Vec<Key>,
+    /// Boards put back from slots this game, in order, for watching it again
+    pub slots_loaded: Vec<Board>,
+    /// Set while watching a game again: loads take the boards it loaded then,
+    /// whatever the slots hold now
+    pub replay_slots: Option<VecDeque<Board>>,
+    /// Time between keys in milliseconds when watching a game again
+    pub watch_ms: u64,
+}
+
+impl<'a> Game<'a> {
+    pub fn new(ui: &'a UI, board: Board) -> Game<'a> {
+        Game {
+            ui: ui,
+            board: board,
+            continue_after_win: false,
+            history: Vec::new(),
+            profile: DEFAULT_PROFILE.to_string(),
+            keep_high_score: false,
+            high_score: 0,
+            paused: false,
+            help: false,
+            moves: 0,
+            last_combo: 0,
+            best_tile: 0,
+            played: time::Duration::from_secs(0),
+            clock_start: Some(time::Instant::now()),
+            tiles_moving: Vec::new(),
+            points_appearing: Vec::new(),
+            animation_start: time::Instant::now(),
+            spawn_start: time::Instant::now(),
+            frame_drawn: time::Instant::now(),
+            animation_ms: 500,
+            fps: DEFAULT_FPS,
+            easing: Easing::Linear,
+            strategy: None,
+            script: VecDeque::new(),
+            held: false,
+            queued: VecDeque::new(),
+            recorder: None,
+            hints: false,
+            hint: None,
+            tag: None,
+            instructions: None,
+            summary: None,
+            bell: false,
+            bell_merge: 512,
+            bells: VecDeque::new(),
+            time_limit: None,
+            time_up: false,
+            // the board decides which of them it has
+            allowed_directions: Direction::all()
+                .iter()
+                .chain(DIAGONALS.iter())
+                .cloned()
+                .collect(),
+            debug_coords: false,
+            events: None,
+            start_tiles: 2,
+            stats: None,
+            goal: None,
+            zen: false,
+            show_next: false,
+            debounce_ms: 0,
+            last_move: None,
+            undo_limit: None,
+            undos_remaining: None,
+            shuffle_limit: 0,
+            shuffles_left: 0,
+            removal_limit: 0,
+            removals_left: 0,
+            cursor: None,
+            analyze: false,
+            preview: false,
+            previewing: None,
+            optimal_moves: 0,
+            hotseat: false,
+            current_player: 0,
+            player_scores: [0; 2],
+            start_board: None,
+            keys_played: Vec::new(),
+            slots_loaded: Vec::new(),
+            replay_slots: None,
+            watch_ms: AUTO_DELAY_MS,
+        }
+    }
+
+    pub fn run(&mut self) {
+        self.start();
+
+        loop {
+            self.tick();
+            self.draw();
+
+            let mut scripted = false;
+            let key = if let Some(key) = self.queued.pop_front() {
+                Some(key)
+            } else if self.tiles_moving.len() > 0
+                || self.points_appearing.len() > 0
+                || !self.bells.is_empty() {
+                // the next frame is drawn on time whether or not keys come in
+                self.wait_frame().map(|key| self.read_ahead(key))
+            } else if !self.script.is_empty() {
+                // replay at a watchable pace, q still quits
+                let key = self.ui.wait_key(Some(AUTO_DELAY_MS));
+                scripted = key.is_none();
+                key.or_else(|| self.next_scripted())
+            } else if let Some(ref strategy) = self.strategy {
+                // give the player time to watch, and to press q
+                let key = self.ui.wait_key(Some(AUTO_DELAY_MS));
+                if key.is_none() && self.board.state == State::Playing && !self.paused && !self.help {
+                    strategy.choose(&self.board).map(Direction::key)
+                } else {
+                    key
+                }
+            } else if self.clock_start.is_some() {
+                // keep the clock ticking, and end a blitz game on time
+                let tick = match self.time_left() {
+                    Some(left) => (left.as_millis() as u64 + 1).min(1000),
+                    None => 1000,
+                };
+                self.ui.wait_key(Some(tick))
+            } else {
+                self.ui.wait_key(None)
+            };
+
+            match key {
+                Some(Key::Char('q')) => {
+                    self.quit();
+                    break;
+                }
+                Some(Key::Char('v')) if self.summary.is_some() => self.watch_replay(),
+                Some(key) if self.summary.is_some() && key != Key::Char('r') => {
+                    // only uncovers the board
+                    self.summary = None;
+                }
+                Some(key) => {
+                    self.step(key);
+                    if !scripted {
+                        self.held = self.paused || self.help;
+                    }
+                }
+                None => {}
+            }
+        }
+    }
+
+    /// The script's next key, None while the player holds it up
+    ///
+    /// A pause or the help that the script itself brings up doesn't hold it
+    /// up, it goes on to the key that closes them.
+    pub fn next_scripted(&mut self) -> Option<Key> {
+        if self.held {
+            return None;
+        }
+        self.script.pop_front()
+    }
+
+    /// Time between animation frames, never longer than the animation
+    /// itself so that its end is drawn on time
+    pub fn frame_ms(&self) -> u64 {
+        (1000 / self.fps.max(1)).min(self.animation_ms).max(1)
+    }
+
+    /// Waits for a key until the next animation frame is due, None once it is
+    fn wait_frame(&self) -> Option<Key> {
+        let due = self.frame_drawn + time::Duration::from_millis(self.frame_ms());
+        loop {
+            let now = time::Instant::now();
+            if now >= due {
+                return None;
+            }
+            // a resize also returns None, keep waiting out the frame after it
+            let remaining = (due - now).as_millis() as u64;
+            if let Some(key) = self.ui.wait_key(Some(remaining.max(1))) {
+                return Some(key);
+            }
+        }
+    }
+
+    /// Catches up with a direction held down during an animation
+    ///
+    /// Repeats already waiting are queued, up to `HELD_REPEATS` of them, so
+    /// the moves follow each other without waiting while letting go of the
+    /// key stops them soon after. A q among them quits at once.
+    fn read_ahead(&mut self, key: Key) -> Key {
+        match key {
+            Key::Up | Key::Down | Key::Left | Key::Right => {}
+            _ => return key,
+        }
+        let mut repeats = 0;
+        for _ in 0..READ_AHEAD_LIMIT {
+            match self.ui.wait_key(Some(0)) {
+                Some(next) if next == key => {
+                    if repeats < HELD_REPEATS {
+                        self.queued.push_back(next);
+                        repeats += 1;
+                    }
+                }
+                Some(Key::Char('q')) => {
+                    self.queued.clear();
+                    return Key::Char('q');
+                }
+                Some(next) => {
+                    self.queued.push_back(next);
+                    break;
+                }
+                None => break,
+            }
+        }
+        key
+    }
+
+    /// Queues `count` bells, after any still waiting to ring
+    fn queue_bells(&mut self, count: u32) {
+        if !self.bell {
+            return;
+        }
+        let gap = time::Duration::from_millis(BELL_GAP_MS);
+        let mut at = match self.bells.back() {
+            Some(&last) => last + gap,
+            None => time::Instant::now(),
+        };
+        for _ in 0..count {
+            self.bells.push_back(at);
+            at += gap;
+        }
+    }
+
+    /// Rings the bells that are due
+    fn ring_bells(&mut self) {
+        let now = time::Instant::now();
+        while self.bells.front().map_or(false, |&at| at <= now) {
+            self.bells.pop_front();
+            self.ui.beep();
+        }
+    }
+
+    /// Places the starting tiles, unless the board already has some
+    pub fn start(&mut self) {
+        self.start_board = Some(self.board.clone());
+        self.keys_played.clear();
+        self.slots_loaded.clear();
+        if self.board.max_tile() > 0 {
+            return;
+        }
+        for _ in 0..self.start_tiles {
+            self.add_tile();
+        }
+    }
+
+    /// Keeps the best score of a profile, which is checked by
+    /// `profile::is_valid_profile`, reading it now and writing it back
+    /// whenever a game beats it
+    pub fn set_profile(&mut self, profile: &str) {
+        self.profile = profile.to_string();
+        self.keep_high_score = true;
+        self.high_score = load_high_score(profile);
+    }
+
+    /// Starts a new game, the best score is kept
+    pub fn reset(&mut self) {
+        self.save_high_score();
+        self.log_stats();
+        self.tiles_moving.clear();
+        self.points_appearing.clear();
+        for column in self.board.grid.iter_mut() {
+            for tile in column.iter_mut() {
+                *tile = Tile::new();
+            }
+        }
+        self.board.score = self.board.start_score;
+        self.board.moved = false;
+        self.board.state = State::Playing;
+        self.continue_after_win = false;
+        self.history.clear();
+        self.last_move = None;
+        self.undos_remaining = self.undo_limit;
+        self.shuffles_left = self.shuffle_limit;
+        self.removals_left = self.removal_limit;
+        self.cursor = None;
+        self.hint = None;
+        self.paused = false;
+        self.help = false;
+        self.moves = 0;
+        self.optimal_moves = 0;
+        self.current_player = 0;
+        self.player_scores = [0; 2];
+        self.last_combo = 0;
+        self.best_tile = 0;
+        self.played = time::Duration::from_secs(0);
+        self.clock_start = Some(time::Instant::now());
+        self.animation_start = time::Instant::now();
+        self.start();
+    }
+
+    /// Applies a single key press to the game, quitting is left to the caller
+    pub fn step(&mut self, key: Key) -> StepResult {
+        let score_before = self.board.score;
+        let state_before = self.board.state;
+        self.board.moved = false;
+        if key != Key::Char('=') && self.previewing.take().is_some() {
+            self.hint = None;
+        }
+
+        // keys that did nothing stay out of `keys_played`, a replay without
+        // the same timing could apply them
+        let mut applied = true;
+        match key {
+            Key::Char('/') => self.toggle_help(),
+            _ if self.help => self.help = false,
+            Key::Char('p') => self.toggle_pause(),
+            Key::Char('`') => self.debug_coords = !self.debug_coords,
+            _ if self.paused => applied = false,
+            _ if self.cursor.is_some() => self.pick(key),
+            Key::Char('c') => self.continue_playing(),
+            Key::Char('u') => self.undo(),
+            Key::Char('?') => self.suggest(),
+            Key::Char('=') if self.preview => self.next_preview(),
+            Key::Char('x') if self.zen => self.clear_smallest(),
+            Key::Char('m') if self.shuffle_limit > 0 => self.shuffle(),
+            Key::Char('e') if self.removal_limit > 0 => self.start_removal(),
+            Key::Char('>') => {
+                // nothing to play again, and watching the game mustn't save over the slot
+                self.save_slot(0);
+                applied = false;
+            }
+            Key::Char('<') => applied = self.load_slot(0),
+            Key::Char('r') => self.reset(),
+            _ => applied = self.play(key),
+        }
+        // the keys start over with a new game, a recording goes on
+        if applied && key != Key::Char('r') {
+            self.keys_played.push(key);
+        }
+        self.update_clock();
+        if self.board.state == State::Playing {
+            self.summary = None;
+            self.time_up = false;
+        }
+        if self.board.state != state_before {
+            let state = self.board.state;
+            self.emit(|events| events.on_state(state));
+        }
+        if applied && self.recorder.is_some() {
+            // what the step led to goes with the key, for --verify-replay
+            let line = format!("{} {} {}", key_name(key), self.board.score, self.board.to_code());
+            if let Some(ref mut recorder) = self.recorder {
+                // a failed write only loses the recording, not the game
+                let _ = writeln!(recorder, "{}", line);
+            }
+        }
+
+        StepResult {
+            moved: self.board.moved,
+            gained: self.board.score.saturating_sub(score_before),
+            state: self.board.state,
+        }
+    }
+
+    /// Moves the board if `key` is a direction that can, returns whether it did
+    fn play(&mut self, key: Key) -> bool {
+        if self.is_bounce(key) {
+            return false;
+        }
+        // finish any on-going animation immediately
+        self.finish_animation();
+        let state_before = self.board.state;
+
+        // start moving
+        if self.board.state != State::Lost && self.board.state != State::Won {
+            let direc = Direction::from_key(key).filter(|direc| self.board.directions().contains(direc));
+            if let Some(direc) = direc {
+                if !self.allowed_directions.contains(&direc) {
+                    self.hint = Some(format!("This drill only allows {}", self.allowed_names()));
+                    return false;
+                }
+                let advised = if self.analyze { best_move(&self.board) } else { None };
+                let snapshot = self.board.clone();
+                let movements = self.board.move_all(direc);
+                self.last_combo = self.board.merges;
+                if self.board.largest_merge > self.best_tile {
+                    self.best_tile = self.board.largest_merge;
+                }
+                if self.board.largest_merge >= self.bell_merge {
+                    self.queue_bells(1);
+                }
+                if self.board.moved {
+                    let gained = self.board.score - snapshot.score;
+                    self.push_history(snapshot);
+                    self.moves += 1;
+                    if advised == Some(direc) {
+                        self.optimal_moves += 1;
+                    }
+                    if self.hotseat {
+                        self.player_scores[self.current_player] += gained;
+                        self.current_player = 1 - self.current_player;
+                    }
+                    self.hint = None;
+                    self.last_move = Some((direc, time::Instant::now()));
+                    self.emit(|events| events.on_move(direc, gained));
+                } else if self.hints {
+                    self.hint = Some("Nothing moves that way".to_string());
+                }
+                self.tiles_moving.extend(movements);
+            }
+            if self.board.moved {
+                self.add_tile();
+            }
+            self.update_state();
+        }
+        self.check_end(state_before);
+        self.animation_start = time::Instant::now();
+        if self.animation_ms == 0 {
+            self.finish_animation();
+        }
+        self.board.moved
+    }
+
+    /// Works out from the board and the goal whether the game is won or lost,
+    /// the one place a move ends the game
+    fn update_state(&mut self) {
+        let goal_reached = self.goal.as_ref().map_or(false, |goal| goal.reached(&self.board, self.moves));
+        let goal_failed = self.goal.as_ref().map_or(false, |goal| goal.failed(&self.board, self.moves));
+        if !self.continue_after_win && (self.board.is_won() || goal_reached) {
+            self.board.state = State::Won;
+        } else if goal_failed {
+            self.board.state = State::Lost;
+        } else if self.board.is_lost() || !self.can_move() {
+            // a drill is stuck too once its directions are blocked
+            if self.zen {
+                self.hint = Some("Stuck, press x to clear the smallest tiles".to_string());
+            } else {
+                self.board.state = State::Lost;
+            }
+        }
+    }
+
+    /// Wraps up if the game is over, with the bells and the summary only
+    /// when it has just ended
+    fn check_end(&mut self, state_before: State) {
+        if self.board.state == State::Playing {
+            return;
+        }
+        let new_high_score = self.board.earned() > self.high_score;
+        if self.board.state != state_before {
+            // a win rings three times, a loss twice
+            self.queue_bells(if self.board.state == State::Won { 3 } else { 2 });
+        }
+        self.save_high_score();
+        // a replay in progress keeps going, there may be an undo coming
+        if self.board.state != state_before && self.script.is_empty() {
+            self.summary = Some(Summary {
+                state: self.board.state,
+                score: self.board.score,
+                start_score: self.board.start_score,
+                moves: self.moves,
+                time: self.play_time(),
+                max_tile: self.board.max_tile(),
+                new_high_score: new_high_score,
+                optimal_percent: self.optimal_percent(),
+                player_scores: if self.hotseat { Some(self.player_scores) } else { None },
+                loser: self.hotseat_loser(),
+            });
+        }
+    }
+
+    /// Whether `key` repeats the latest move too soon to have been meant
+    fn is_bounce(&self, key: Key) -> bool {
+        match self.last_move {
+            Some((direc, at)) if self.debounce_ms > 0 && direc.key() == key => {
+                at.elapsed() < time::Duration::from_millis(self.debounce_ms)
+            }
+            _ => false,
+        }
+    }
+
+    /// Percentage of moves that matched the solver's, when they are analyzed
+    pub fn optimal_percent(&self) -> Option<usize> {
+        match self.moves {
+            _ if !self.analyze => None,
+            0 => Some(0),
+            moves => Some(self.optimal_moves * 100 / moves),
+        }
+    }
+
+    /// The player who lost a finished hotseat match: whoever filled the board,
+    /// otherwise the lower score; None while it goes on, or on a draw
+    pub fn hotseat_loser(&self) -> Option<usize> {
+        // a stuck board is over before anyone has pressed a key on it
+        let stuck = !self.zen && !self.time_up && self.moves > 0 && !self.can_move();
+        if !self.hotseat || (self.board.state == State::Playing && !stuck) {
+            return None;
+        }
+        if stuck && self.board.state != State::Won {
+            // turns alternate, so the last move was the other player's
+            return Some(1 - self.current_player);
+        }
+        match self.player_scores[0].cmp(&self.player_scores[1]) {
+            Ordering::Less => Some(0),
+            Ordering::Greater => Some(1),
+            Ordering::Equal => None,
+        }
+    }
+
+    /// Whether any allowed direction changes the board
+    fn can_move(&self) -> bool {
+        self.board.available_moves().iter().any(|direc| self.allowed_directions.contains(direc))
+    }
+
+    /// Previews the next move that would change the board, after the one
+    /// being previewed
+    fn next_preview(&mut self) {
+        self.finish_animation();
+        if self.board.state == State::Lost || self.board.state == State::Won {
+            return;
+        }
+        let moves: Vec<Direction> = self.board.directions()
+            .into_iter()
+            .filter(|direc| self.allowed_directions.contains(direc) && self.preview_board(*direc).is_some())
+            .collect();
+        if moves.is_empty() {
+            return;
+        }
+        let next = match self.previewing.and_then(|direc| moves.iter().position(|&m| m == direc)) {
+            Some(i) => moves[(i + 1) % moves.len()],
+            None => moves[0],
+        };
+        self.previewing = Some(next);
+        self.hint = Some(format!("Preview of {}, press again for the next move", next.name()));
+    }
+
+    /// The board a move in `direc` would leave, with the tile that would come
+    /// next, None if nothing would move. The board's RNG is copied with it, so
+    /// making the move gives exactly this board.
+    pub fn preview_board(&self, direc: Direction) -> Option<Board> {
+        let mut board = self.board.clone();
+        board.moved = false;
+        board.move_all(direc);
+        if !board.moved {
+            return None;
+        }
+        if let Some(appearing) = board.add_tile() {
+            board.grid[appearing.position.x][appearing.position.y].set(appearing.value);
+        }
+        for tile in board.grid.iter_mut().flat_map(|column| column.iter_mut()) {
+            *tile = Tile::from_value(tile.value());
+        }
+        Some(board)
+    }
+
+    /// The allowed directions, in a fixed order
+    fn allowed_names(&self) -> String {
+        self.board.directions()
+            .iter()
+            .filter(|direc| self.allowed_directions.contains(direc))
+            .map(|direc| direc.name())
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+
+    /// Ends a blitz game that is out of time and rings the bells that are
+    /// due, before each frame of whatever loop is driving the game
+    pub fn tick(&mut self) {
+        self.check_time_limit();
+        self.ring_bells();
+    }
+
+    /// Time left in a blitz game
+    pub fn time_left(&self) -> Option<time::Duration> {
+        self.time_limit.map(|limit| {
+            let played = self.play_time();
+            if played < limit { limit - played } else { time::Duration::from_secs(0) }
+        })
+    }
+
+    /// Ends a blitz game that has run out of time
+    fn check_time_limit(&mut self) {
+        if self.board.state == State::Playing && self.time_left() == Some(time::Duration::from_secs(0)) {
+            self.finish_animation();
+            self.board.state = State::Lost;
+            self.time_up = true;
+            self.check_end(State::Playing);
+            self.update_clock();
+            self.emit(|events| events.on_state(State::Lost));
+        }
+    }
+
+    /// Passes an event to the sink, if there is one
+    fn emit<F: FnOnce(&mut EventSink)>(&mut self, f: F) {
+        if let Some(ref mut events) = self.events {
+            f(&mut **events);
+        }
+    }
+
+    /// Puts a new tile on the board straight away, after the move so it can't
+    /// take part in it; it is drawn growing in from `points_appearing` and
+    /// shown in its cell once that is done, see `finish_spawning`
+    fn add_tile(&mut self) {
+        if let Some(appearing) = self.board.add_tile() {
+            let (pos, value) = ((appearing.position.x, appearing.position.y), appearing.value);
+            // whatever reads the board from now on, such as the check for a
+            // lost game right after this, sees the tile
+            let tile = &mut self.board.grid[pos.0][pos.1];
+            tile.set(value);
+            tile.set_pending(true);
+            self.emit(|events| events.on_spawn(pos, value));
+            self.points_appearing.push(appearing);
+            // tiles sliding now have to be in place first, the spawn ends with the move
+            let slide_ms = if self.tiles_moving.is_empty() { 0 } else { self.animation_ms - self.spawn_ms() };
+            self.spawn_start = time::Instant::now() + time::Duration::from_millis(slide_ms);
+        }
+    }
+
+    /// Empties every cell holding the smallest tile on a stuck board, for zen
+    /// mode; the score stays as it is
+    fn clear_smallest(&mut self) {
+        self.finish_animation();
+        if self.board.state != State::Playing || self.can_move() {
+            return;
+        }
+        let smallest = self.board.grid.iter()
+            .flat_map(|column| column.iter())
+            .map(|tile| tile.value())
+            .filter(|&value| value > 0)
+            .min();
+        if let Some(smallest) = smallest {
+            let snapshot = self.board.clone();
+            self.push_history(snapshot);
+            for tile in self.board.grid.iter_mut().flat_map(|column| column.iter_mut()) {
+                if tile.value() == smallest {
+                    *tile = Tile::new();
+                }
+            }
+            self.hint = None;
+        }
+    }
+
+    /// Rearranges the tiles, one of a limited number of times a game
+    fn shuffle(&mut self) {
+        self.finish_animation();
+        if self.board.state != State::Playing {
+            return;
+        }
+        if self.shuffles_left == 0 {
+            self.hint = Some("No shuffles left".to_string());
+            return;
+        }
+        let snapshot = self.board.clone();
+        if self.board.shuffle() {
+            self.push_history(snapshot);
+            self.shuffles_left -= 1;
+            self.hint = None;
+            // the new arrangement may have nowhere to go
+            self.update_state();
+            self.check_end(State::Playing);
+        } else {
+            self.hint = Some("Can't shuffle this board".to_string());
+        }
+    }
+
+    /// Shows a cursor for picking a tile to remove, on a board being played
+    /// or one lost for having nowhere to move
+    fn start_removal(&mut self) {
+        self.finish_animation();
+        let stuck = self.board.state == State::Lost && !self.time_up && !self.can_move();
+        if self.board.state != State::Playing && !stuck {
+            return;
+        }
+        if self.removals_left == 0 {
+            self.hint = Some("No removals left".to_string());
+            return;
+        }
+        self.cursor = Some(Point { x: 0, y: 0 });
+        self.hint = Some("Arrows pick a tile, e removes it, any other key cancels".to_string());
+    }
+
+    /// Moves the removal cursor, removes the tile under it or cancels
+    fn pick(&mut self, key: Key) {
+        let mut cursor = match self.cursor {
+            Some(cursor) => cursor,
+            None => return,
+        };
+        match key {
+            Key::Up => cursor.y = cursor.y.saturating_sub(1),
+            Key::Down => cursor.y = (cursor.y + 1).min(self.board.size.rows - 1),
+            Key::Left => cursor.x = cursor.x.saturating_sub(1),
+            Key::Right => cursor.x = (cursor.x + 1).min(self.board.size.cols - 1),
+            Key::Char('e') if self.board.grid[cursor.x][cursor.y].is_empty() => {
+                self.hint = Some("Pick a tile".to_string());
+            }
+            Key::Char('e') => {
+                let snapshot = self.board.clone();
+                self.push_history(snapshot);
+                self.board.grid[cursor.x][cursor.y] = Tile::new();
+                self.removals_left -= 1;
+                self.cursor = None;
+                self.hint = None;
+                // a lost board with room again is back in play
+                if self.board.state == State::Lost && self.can_move() {
+                    self.board.state = State::Playing;
+                    self.summary = None;
+                }
+                return;
+            }
+            _ => {
+                self.cursor = None;
+                self.hint = None;
+                return;
+            }
+        }
+        self.cursor = Some(cursor);
+    }
+
+    /// Shows the move the solver would make, without making it
+    fn suggest(&mut self) {
+        self.hint = Some(match best_move(&self.board) {
+            Some(direc) => format!("Try: {}", direc.arrow()),
+            None => "No moves".to_string(),
+        });
+    }
+
+    fn toggle_pause(&mut self) {
+        // freeze on a finished frame
+        self.finish_animation();
+        self.paused = !self.paused;
+    }
+
+    fn toggle_help(&mut self) {
+        self.finish_animation();
+        self.help = !self.help;
+    }
+
+    /// The keys that do something in this game, in the order help lists them
+    fn active_keys(&self) -> Vec<Key> {
+        let mut keys: Vec<Key> = self.board.directions()
+            .iter()
+            .filter(|direc| self.allowed_directions.contains(direc))
+            .map(|&direc| direc.key())
+            .collect();
+        if self.undo_limit != Some(0) && !self.hotseat {
+            keys.push(Key::Char('u'));
+        }
+        if self.board.state == State::Won {
+            keys.push(Key::Char('c'));
+        }
+        keys.extend_from_slice(&[Key::Char('?'), Key::Char('p'), Key::Char('r')]);
+        if self.preview {
+            keys.push(Key::Char('='));
+        }
+        if self.zen {
+            keys.push(Key::Char('x'));
+        }
+        if self.shuffle_limit > 0 {
+            keys.push(Key::Char('m'));
+        }
+        if self.removal_limit > 0 {
+            keys.push(Key::Char('e'));
+        }
+        keys.extend_from_slice(&[Key::Char('>'), Key::Char('<'),
+                                 Key::Char('`'), Key::Char('/'), Key::Char('q')]);
+        keys
+    }
+
+    /// The rules this game is played by, shown under the keys in help
+    fn help_notes(&self) -> Vec<String> {
+        let mut notes = vec![format!("Win target: {}", self.board.win_target)];
+        if let Some(ref goal) = self.goal {
+            notes.push(goal.progress(&self.board, self.moves));
+        }
+        if let Some(limit) = self.time_limit {
+            let secs = limit.as_secs();
+            notes.push(format!("Blitz: {}:{:02} to play", secs / 60, secs % 60));
+        }
+        if self.hotseat {
+            notes.push("Hotseat: turns alternate, filling the board loses".to_string());
+        }
+        if self.zen {
+            notes.push("Zen: the game is never lost".to_string());
+        }
+        if self.board.gravity {
+            notes.push("Gravity: tiles fall after every move".to_string());
+        }
+        if let Some(limit) = self.undo_limit {
+            notes.push(format!("Undos: {} a game", limit));
+        }
+        if self.analyze {
+            notes.push("Moves are compared with the solver's".to_string());
+        }
+        notes
+    }
+
+    /// Runs the clock only while the game is being played
+    fn update_clock(&mut self) {
+        let playing = self.board.state == State::Playing && !self.paused && !self.help;
+        match self.clock_start {
+            Some(start) if !playing => {
+                self.played += start.elapsed();
+                self.clock_start = None;
+            }
+            None if playing => self.clock_start = Some(time::Instant::now()),
+            _ => {}
+        }
+    }
+
+    pub fn play_time(&self) -> time::Duration {
+        match self.clock_start {
+            Some(start) => self.played + start.elapsed(),
+            None => self.played,
+        }
+    }
+
+    fn push_history(&mut self, board: Board) {
+        if self.history.len() >= HISTORY_LIMIT {
+            self.history.remove(0);
+        }
+        self.history.push(board);
+    }
+
+    /// Restores the board as it was before the latest move
+    pub fn undo(&mut self) {
+        self.finish_animation();
+        if self.hotseat {
+            // the scores and turns of both players would have to go back too
+            self.hint = Some("No undos in a hotseat match".to_string());
+            return;
+        }
+        if self.undos_remaining == Some(0) {
+            self.hint = Some("No undos left".to_string());
+            return;
+        }
+        if let Some(board) = self.history.pop() {
+            self.board = board;
+            self.undos_remaining = self.undos_remaining.map(|left| left - 1);
+        }
+    }
+
+    /// Plays this game again from its start, in a `Replay` the player can
+    /// pause and step through
+    fn watch_replay(&mut self) {
+        let start = match self.start_board {
+            Some(ref board) => board.clone(),
+            None => return,
+        };
+        let mut game = Game::new(self.ui, start);
+        // the rules have to be the same for the keys to play out the same
+        game.animation_ms = self.animation_ms;
+        game.fps = self.fps;
+        game.easing = self.easing;
+        game.start_tiles = self.start_tiles;
+        game.allowed_directions = self.allowed_directions.clone();
+        game.zen = self.zen;
+        game.hotseat = self.hotseat;
+        game.undo_limit = self.undo_limit;
+        game.undos_remaining = self.undo_limit;
+        game.shuffle_limit = self.shuffle_limit;
+        game.shuffles_left = self.shuffle_limit;
+        game.removal_limit = self.removal_limit;
+        game.removals_left = self.removal_limit;
+        game.goal = self.goal.take();
+        // it can't beat the best score, so none is stored
+        game.profile = self.profile.clone();
+        game.high_score = self.high_score;
+
+        game.replay_slots = Some(self.slots_loaded.iter().cloned().collect());
+
+        let mut replay = Replay::new(game, self.keys_played.clone(), self.watch_ms);
+        replay.run();
+        self.goal = replay.game.goal.take();
+    }
+
+    /// Writes the board as JSON to a numbered slot of the profile
+    pub fn save_slot(&mut self, slot: usize) {
+        self.finish_animation();
+        let result = match slot_path(&self.profile, slot) {
+            Some(path) => store_slot(&path, &self.board),
+            None => Err("no home directory".to_string()),
+        };
+        self.hint = Some(match result {
+            Ok(()) => format!("Saved to slot {}", slot),
+            Err(e) => format!("Can't save to slot {}: {}", slot, e),
+        });
+    }
+
+    /// Puts back the board saved in a slot, the move before it can be undone;
+    /// returns whether there was one to put back
+    pub fn load_slot(&mut self, slot: usize) -> bool {
+        self.finish_animation();
+        let loaded = match self.replay_slots {
+            Some(ref mut boards) => boards.pop_front().ok_or_else(|| "nothing was loaded".to_string()),
+            None => {
+                let text = match slot_path(&self.profile, slot).and_then(|path| fs::read_to_string(path).ok()) {
+                    Some(text) => text,
+                    None => {
+                        self.hint = Some(format!("No save in slot {}", slot));
+                        return false;
+                    }
+                };
+                match Board::from_json(&text) {
+                    Ok(ref loaded) if loaded.size != self.board.size => {
+                        Err(format!("it is {}x{}", loaded.size.cols, loaded.size.rows))
+                    }
+                    other => other,
+                }
+            }
+        };
+        match loaded {
+            Ok(loaded) => {
+                self.slots_loaded.push(loaded.clone());
+                let snapshot = self.board.clone();
+                self.push_history(snapshot);
+                // the new tiles still come from this game's seed
+                self.board.grid = loaded.grid;
+                self.board.walls = loaded.walls;
+                // with the handicap it was saved with, which is part of the score
+                self.board.score = loaded.score;
+                self.board.start_score = loaded.start_score;
+                self.board.state = loaded.state;
+                self.cursor = None;
+                self.summary = None;
+                self.hint = Some(format!("Loaded slot {}", slot));
+                true
+            }
+            Err(e) => {
+                self.hint = Some(format!("Can't load slot {}: {}", slot, e));
+                false
+            }
+        }
+    }
+
+    fn save_high_score(&mut self) {
+        // only what was scored in play counts, not a handicap
+        if self.board.earned() > self.high_score {
+            self.high_score = self.board.earned();
+            if self.keep_high_score {
+                store_high_score(&self.profile, self.high_score);
+            }
+        }
+    }
+
+    /// Wraps up a game that is being left, quitting is left to the caller
+    pub fn quit(&mut self) {
+        self.save_high_score();
+        self.log_stats();
+    }
+
+    /// Logs the game being left, unless not a single move was made
+    fn log_stats(&mut self) {
+        if self.moves == 0 {
+            return;
+        }
+        let outcome = match self.board.state {
+            State::Lost => Outcome::Lost,
+            State::Won => Outcome::Won,
+            State::Playing if self.continue_after_win => Outcome::Won,
+            // a loaded board can be stuck before any move finds out
+            State::Playing if !self.zen && !self.can_move() => Outcome::Lost,
+            State::Playing => Outcome::Quit,
+        };
+        if let Some(ref mut stats) = self.stats {
+            // a failed write only loses the line, not the game
+            let _ = stats.log(&self.board, self.moves, outcome);
+        }
+    }
+
+    fn continue_playing(&mut self) {
+        if self.board.state == State::Won {
+            self.board.state = State::Playing;
+            self.continue_after_win = true;
+        }
+    }
+
+    pub fn finish_animation(&mut self) {
+        self.finish_moving();
+        self.finish_spawning();
+    }
+
+    fn finish_moving(&mut self) {
+        for m in &self.tiles_moving {
+            self.board.grid[m.pnew.x][m.pnew.y].set_pending(false);
+        }
+        self.tiles_moving.truncate(0);
+    }
+
+    fn finish_spawning(&mut self) {
+        // already on the board, only its cell still shows it empty
+        for a in &self.points_appearing {
+            self.board.grid[a.position.x][a.position.y].set_pending(false);
+        }
+        self.points_appearing.truncate(0);
+    }
+
+    fn get_progress(&self) -> f32 {
+        // how much of the animation has been done
+        progress(self.animation_start.elapsed(), self.animation_ms)
+    }
+
+    /// Time new tiles take to grow in, the part of the animation after the slide
+    fn spawn_ms(&self) -> u64 {
+        (self.animation_ms as f32 * (1.0 - SLIDE_SHARE)) as u64
+    }
+
+    /// How far new tiles have grown in, None while they wait for the slide
+    fn spawn_progress(&self) -> Option<f32> {
+        let now = time::Instant::now();
+        if now < self.spawn_start {
+            return None;
+        }
+        Some(progress(now - self.spawn_start, self.spawn_ms()))
+    }
+
+    fn draw_moving(&mut self) {
+        let ratio = self.get_progress();
+        if ratio > 0.99 {
+            self.finish_moving();
+        }
+        let slide = self.easing.apply(ratio / SLIDE_SHARE);
+        for m in &self.tiles_moving {
+            let col = m.pold.x as f32 + (m.pnew.x as f32 - m.pold.x as f32) * slide;
+            let row = m.pold.y as f32 + (m.pnew.y as f32 - m.pold.y as f32) * slide;
+
+            self.ui.draw_tile_between(m.tile, col, row, None);
+        }
+
+        match self.spawn_progress() {
+            Some(grown) if grown > 0.99 => self.finish_spawning(),
+            Some(grown) => {
+                for a in &self.points_appearing {
+                    self.ui.draw_tile(a.position.x, a.position.y,
+                                      Tile::from_value(a.value),
+                                      Some(grown));
+                }
+            }
+            None => {}
+        }
+    }
+
+    /// Draws merged tiles growing past their cell and settling back once
+    /// the slide is over, on top of the grid
+    fn draw_merging(&self) {
+        let ratio = self.get_progress();
+        if ratio <= SLIDE_SHARE {
+            return;
+        }
+        let t = (ratio - SLIDE_SHARE) / (1.0 - SLIDE_SHARE);
+        let scale = 1.0 + (POP_SCALE - 1.0) * (t * std::f32::consts::PI).sin();
+        for m in self.tiles_moving.iter().filter(|m| m.merged) {
+            let tile = Tile::from_value(self.board.grid[m.pnew.x][m.pnew.y].value());
+            self.ui.draw_tile(m.pnew.x, m.pnew.y, tile, Some(scale));
+        }
+    }
+
+    fn draw(&mut self) {
+        self.ui.clear();
+        self.draw_frame();
+        self.ui.present();
+    }
+
+    /// Draws the game on a cleared screen, presenting it is left to the
+    /// caller so other games can share the screen
+    pub fn draw_frame(&mut self) {
+        // frames are paced from their start, however long drawing takes
+        self.frame_drawn = time::Instant::now();
+        let instructions = self.instructions.as_ref()
+            .map_or("←,↑,→,↓, u, p, r, ? or q, / for help", String::as_str);
+        self.ui.draw_instructions(match self.tag {
+            Some(ref tag) => format!("{}  {}", tag, instructions),
+            None => instructions.to_string(),
+        });
+        if self.hotseat {
+            let mark = |player| if self.current_player == player { "▶" } else { "" };
+            self.ui.draw_score(format!("{}P1: {}  {}P2: {}", mark(0), self.player_scores[0],
+                                       mark(1), self.player_scores[1]));
+        } else {
+            self.ui.draw_score(format!("Score: {}", self.board.score));
+        }
+        self.ui.draw_high_score(format!("Best: {}", self.high_score));
+        self.ui.draw_title(format!("2048: {} ({})", self.board.score, self.board.max_tile()));
+        let secs = self.play_time().as_secs();
+        let mut stats = format!("Moves: {}  Time: {}:{:02}  Combo: {}  Top merge: {}  Best tile: {}",
+                                self.moves, secs / 60, secs % 60,
+                                self.last_combo, self.best_tile, self.board.max_tile());
+        if let Some(left) = self.time_left() {
+            // rounded up, so 0:00 only shows once the time is up
+            let left = (left.as_millis() as u64 + 999) / 1000;
+            stats.push_str(&format!("  Left: {}:{:02}", left / 60, left % 60));
+        }
+        if self.removal_limit > 0 {
+            stats.push_str(&format!("  Removals: {}", self.removals_left));
+        }
+        if self.shuffle_limit > 0 {
+            stats.push_str(&format!("  Shuffles: {}", self.shuffles_left));
+        }
+        if let Some(left) = self.undos_remaining {
+            stats.push_str(&format!("  Undos: {}", left));
+        }
+        if self.show_next {
+            stats.push_str(&format!("  Next: {}", self.board.next_spawn()));
+        }
+        if let Some(ref goal) = self.goal {
+            stats.push_str(&format!("  {}", goal.progress(&self.board, self.moves)));
+        }
+        self.ui.draw_stats(stats);
+        self.ui.draw_bg(0, 0);
+
+        match self.previewing.and_then(|direc| self.preview_board(direc)) {
+            Some(preview) => self.ui.draw_preview(&preview.grid),
+            None => {
+                self.draw_moving();
+                self.ui.draw_grid(&self.board.grid);
+            }
+        }
+        for wall in &self.board.walls {
+            self.ui.draw_wall(wall.x, wall.y);
+        }
+
+        self.draw_merging();
+
+        if self.debug_coords {
+            self.ui.draw_debug(&self.board.grid);
+        }
+
+        if let Some(cursor) = self.cursor {
+            self.ui.draw_cursor(cursor.x, cursor.y);
+        }
+
+        if let Some(ref hint) = self.hint {
+            self.ui.draw_hint(hint.clone());
+        }
+
+        if self.help {
+            self.ui.draw_help(&self.active_keys(), &self.help_notes());
+        } else if self.paused {
+            self.ui.draw_paused();
+        } else if self.board.state == State::Lost && self.time_up {
+            self.ui.draw_time_up();
+        } else if self.board.state == State::Lost {
+            self.ui.draw_lost();
+        } else if self.board.state == State::Won {
+            self.ui.draw_won();
+        } else if self.continue_after_win {
+            self.ui.draw_continuing();
+        }
+
+        // after the tiles settle, so the final board is what it covers
+        if let Some(ref summary) = self.summary {
+            if self.tiles_moving.is_empty() && self.points_appearing.is_empty() {
+                self.ui.draw_summary(summary);
+            }
+        }
+    }
+}
+
+/// Fraction of an animation lasting `duration_ms` done after `elapsed`, within [0, 1]
+fn progress(elapsed: time::Duration, duration_ms: u64) -> f32 {
+    if duration_ms == 0 {
+        return 1.0;
+    }
+    let elapsed_ms = elapsed.as_millis();
+    let ratio = elapsed_ms as f64 / duration_ms as f64;
+    if ratio > 1.0 {
+        1.0
+    } else {
+        ratio as f32
+    }
+}
+
+/// Where a profile's best score is kept between runs
+fn high_score_path(profile: &str) -> Option<PathBuf> {
+    Some(data_dir(profile)?.join("highscore"))
+}
+
+/// Where a profile keeps its files, under $XDG_DATA_HOME
+fn data_dir(profile: &str) -> Option<PathBuf> {
+    let data_home = match env::var_os("XDG_DATA_HOME") {
+        Some(dir) => PathBuf::from(dir),
+        None => PathBuf::from(env::var_os("HOME")?).join(".local").join("share"),
+    };
+    Some(profile_dir(data_home, profile))
+}
+
+fn slot_path(profile: &str, slot: usize) -> Option<PathBuf> {
+    Some(data_dir(profile)?.join("slots").join(format!("{}.json", slot)))
+}
+
+fn store_slot(path: &PathBuf, board: &Board) -> Result<(), String> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+    }
+    fs::write(path, format!("{}\n", board.to_json())).map_err(|e| e.to_string())
+}
+
+/// Reads the stored best score, a missing or unreadable file counts as 0
+fn load_high_score(profile: &str) -> usize {
+    high_score_path(profile)
+        .and_then(|path| fs::read_to_string(path).ok())
+        .and_then(|text| text.trim().parse().ok())
+        .unwrap_or(0)
+}
+
+/// Writes the best score, failures are ignored as the game can go on without it
+fn store_high_score(profile: &str, score: usize) {
+    if let Some(path) = high_score_path(profile) {
+        if let Some(dir) = path.parent() {
+            let _ = fs::create_dir_all(dir);
+        }
+        let _ = fs::write(path, format!("{}\n", score));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use board::BoardSize;
+    use ui::NullUI;
+
+    /// A 2 in the top right corner
+    const CORNER: &str = "0 0 0 2\n0 0 0 0\n0 0 0 0\n0 0 0 0";
+
+    /// A game started from `text` as `Board::from_text` reads it, without
+    /// animation, and a new game would start empty
+    fn game_on<'a>(ui: &'a UI, text: &str) -> Game<'a> {
+        let board = Board::from_text(text, BoardSize::default(), 1).unwrap();
+        let mut game = Game::new(ui, board);
+        game.animation_ms = 0;
+        game.start_tiles = 0;
+        game.start();
+        game
+    }
+
+    /// The board after playing `keys` from the start of a game with `seed`
+    fn played(seed: u64, animation_ms: u64, keys: &[Key]) -> Board {
+        let ui = NullUI::new(::std::iter::empty());
+        let mut game = Game::new(&ui, Board::new(BoardSize::default(), seed));
+        game.animation_ms = animation_ms;
+        game.start();
+        for &key in keys {
+            game.step(key);
+        }
+        game.finish_animation();
+        game.board
+    }
+
+    #[test]
+    fn same_seed_and_keys_same_game() {
+        let keys = [Key::Left, Key::Up, Key::Right, Key::Down, Key::Left, Key::Left, Key::Up, Key::Right];
+        let first = played(42, 0, &keys);
+        assert!(first.grid == played(42, 0, &keys).grid);
+        assert_eq!(first.score, played(42, 0, &keys).score);
+        // animating only changes what is shown on the way
+        assert!(first.grid == played(42, 150, &keys).grid);
+    }
+
+    #[test]
+    fn progress_saturates() {
+        assert_eq!(progress(time::Duration::from_secs(100_000), 500), 1.0);
+        assert_eq!(progress(time::Duration::from_secs(u64::max_value()), 500), 1.0);
+        assert_eq!(progress(time::Duration::from_millis(250), 500), 0.5);
+        assert_eq!(progress(time::Duration::from_millis(0), 500), 0.0);
+        assert_eq!(progress(time::Duration::from_millis(3), 0), 1.0);
+    }
+
+    #[test]
+    fn only_applied_keys_are_played() {
+        let ui = NullUI::new(::std::iter::empty());
+        let mut game = game_on(&ui, CORNER);
+        game.debounce_ms = 60_000;
+        game.step(Key::Left);
+        // too soon after the last left, and a key bound to nothing
+        game.step(Key::Left);
+        game.step(Key::Char('z'));
+        assert_eq!(game.keys_played, vec![Key::Left]);
+    }
+
+    #[test]
+    fn keys_while_paused_are_not_played() {
+        let ui = NullUI::new(::std::iter::empty());
+        let mut game = game_on(&ui, CORNER);
+        game.step(Key::Char('p'));
+        game.step(Key::Left);
+        game.step(Key::Char('p'));
+        assert_eq!(game.keys_played, vec![Key::Char('p'), Key::Char('p')]);
+    }
+
+    #[test]
+    fn best_tile_follows_the_merges() {
+        let ui = NullUI::new(::std::iter::empty());
+        assert_eq!(Board::new(BoardSize::default(), 1).max_tile(), 0);
+        let mut game = game_on(&ui, "2 2 4 8\n0 0 0 0\n0 0 0 0\n0 0 0 0");
+        assert_eq!(game.board.max_tile(), 8);
+        // 4 4 8, then 8 8, then 16, whatever new tiles come
+        for &expected in &[8, 8, 16] {
+            game.step(Key::Left);
+            assert_eq!(game.board.max_tile(), expected);
+        }
+        assert_eq!(game.best_tile, 16);
+    }
+
+    #[test]
+    fn start_places_exactly_the_start_tiles() {
+        let ui = NullUI::new(::std::iter::empty());
+        for seed in 0..20 {
+            for &count in &[1, 2, 5, 15] {
+                let mut game = Game::new(&ui, Board::new(BoardSize::default(), seed));
+                game.animation_ms = 0;
+                game.start_tiles = count;
+                game.start();
+                let tiles = game.board.grid.iter()
+                    .flat_map(|column| column.iter())
+                    .filter(|tile| !tile.is_empty())
+                    .count();
+                assert_eq!(tiles, count, "seed {}", seed);
+            }
+        }
+    }
+
+    #[test]
+    fn won_and_lost_at_the_boundary() {
+        let ui = NullUI::new(::std::iter::empty());
+        let mut game = game_on(&ui, "1024 1024 0 0\n0 0 0 0\n0 0 0 0\n0 0 0 0");
+        assert!(!game.board.is_won());
+        game.step(Key::Left);
+        assert!(game.board.is_won());
+        assert_eq!(game.board.state, State::Won);
+
+        // full, and the one merge left leaves another, wherever the new tile goes
+        let mut game = game_on(&ui, "2 4 2 4\n4 2 4 2\n2 4 2 4\n4 4 8 16");
+        assert!(!game.board.is_lost());
+        game.step(Key::Left);
+        assert!(!game.board.is_lost());
+        assert_eq!(game.board.state, State::Playing);
+        // played on, it ends as lost the move nothing can follow
+        while game.board.state == State::Playing {
+            let moves = game.moves;
+            game.step(game.board.available_moves()[0].key());
+            assert!(game.moves > moves);
+        }
+        assert_eq!(game.board.state, State::Lost);
+        assert!(game.board.is_lost());
+
+        let stuck = Board::from_text("2 4 2 4\n4 2 4 2\n2 4 2 4\n4 2 4 2", BoardSize::default(), 1).unwrap();
+        assert!(stuck.is_lost());
+        assert!(!stuck.is_won());
+    }
+
+    #[test]
+    fn handicap_stays_out_of_the_best_score() {
+        let ui = NullUI::new(::std::iter::empty());
+        let mut game = game_on(&ui, "2 2 0 0\n0 0 0 0\n0 0 0 0\n0 0 0 0");
+        game.board.start_score = 1000;
+        game.board.score = 1000;
+        game.high_score = 3;
+        game.step(Key::Left);
+        // the merge adds its own points on top
+        assert_eq!(game.board.score, 1004);
+        assert_eq!(game.board.earned(), 4);
+        game.quit();
+        assert_eq!(game.high_score, 4);
+    }
+
+    #[test]
+    fn easing_stays_between_the_ends() {
+        for &easing in &[Easing::Linear, Easing::EaseOut, Easing::EaseInOut] {
+            assert_eq!(easing.apply(0.0), 0.0, "{}", easing.name());
+            assert_eq!(easing.apply(1.0), 1.0, "{}", easing.name());
+            // out of range times are held at the ends
+            assert_eq!(easing.apply(-0.5), 0.0, "{}", easing.name());
+            assert_eq!(easing.apply(1.5), 1.0, "{}", easing.name());
+            let mut last = 0.0;
+            for step in 0..=100 {
+                let eased = easing.apply(step as f32 / 100.0);
+                assert!(eased >= 0.0 && eased <= 1.0, "{} at {}: {}", easing.name(), step, eased);
+                assert!(eased >= last, "{} goes back at {}", easing.name(), step);
+                last = eased;
+            }
+        }
+    }
+
+    fn tile_sum(board: &Board) -> usize {
+        board.grid.iter().flat_map(|column| column.iter()).map(|tile| tile.value()).sum()
+    }
+
+    #[test]
+    fn rapid_moves_commit_each_spawn_once() {
+        let ui = NullUI::new(::std::iter::empty());
+        let mut game = Game::new(&ui, Board::new(BoardSize::default(), 3));
+        game.animation_ms = 60_000;
+        game.debounce_ms = 0;
+        game.start();
+        let mut sum = tile_sum(&game.board);
+        let keys = [Key::Left, Key::Up, Key::Right, Key::Down];
+        // every key comes while the last move is still being animated
+        for &key in keys.iter().cycle().take(40) {
+            game.step(key);
+            if game.board.moved {
+                // merges keep the sum, so only the one new tile adds to it
+                assert_eq!(game.points_appearing.len(), 1);
+                let spawned = &game.points_appearing[0];
+                assert!(game.board.grid[spawned.position.x][spawned.position.y].is_pending());
+                sum += spawned.value;
+            }
+            assert_eq!(tile_sum(&game.board), sum);
+        }
+        game.finish_animation();
+        assert_eq!(tile_sum(&game.board), sum);
+        assert!(game.board.grid.iter().flat_map(|column| column.iter()).all(|tile| !tile.is_pending()));
+    }
+
+    #[test]
+    fn spawned_tile_sits_out_its_own_move() {
+        for seed in 0..20 {
+            let ui = NullUI::new(::std::iter::empty());
+            let mut game = game_on(&ui, "2 2 0 0\n0 0 0 0\n0 0 0 0\n0 0 0 0");
+            game.board.reseed(seed);
+            game.animation_ms = 60_000;
+            game.step(Key::Left);
+            let (x, y, value) = {
+                let spawned = &game.points_appearing[0];
+                (spawned.position.x, spawned.position.y, spawned.value)
+            };
+            // the new tile keeps its value and the merged 4 stays where it went
+            assert_eq!(game.board.grid[x][y].value(), value);
+            assert_eq!(game.board.grid[0][0].value(), 4);
+            assert_eq!(tile_sum(&game.board), 4 + value);
+            game.finish_animation();
+            assert_eq!(tile_sum(&game.board), 4 + value);
+        }
+    }
+
+    #[test]
+    fn replay_goes_on_through_its_own_pause() {
+        let ui = NullUI::new(::std::iter::empty());
+        let mut game = game_on(&ui, CORNER);
+        let keys = vec![Key::Left, Key::Char('p'), Key::Down, Key::Char('p'), Key::Up];
+        game.script = keys.iter().cloned().collect();
+        while let Some(key) = game.next_scripted() {
+            game.step(key);
+        }
+        assert!(!game.paused);
+        // the down came while paused
+        assert_eq!(game.moves, 2);
+        assert_eq!(game.keys_played, vec![Key::Left, Key::Char('p'), Key::Char('p'), Key::Up]);
+
+        // a pause of the player's own holds it up
+        game.script.push_back(Key::Down);
+        game.held = true;
+        assert_eq!(game.next_scripted(), None);
+    }
+
+    #[test]
+    fn watching_again_loads_what_was_loaded_then() {
+        let ui = NullUI::new(::std::iter::empty());
+        let saved = Board::from_text("2 4 8 16\n0 0 0 0\n0 0 0 0\n0 0 0 0", BoardSize::default(), 1).unwrap();
+        let mut game = game_on(&ui, CORNER);
+        game.replay_slots = Some(vec![saved.clone()].into_iter().collect());
+        for &key in &[Key::Left, Key::Char('<'), Key::Down, Key::Char('<')] {
+            game.step(key);
+        }
+        // the second load had nothing to put back
+        assert_eq!(game.keys_played, vec![Key::Left, Key::Char('<'), Key::Down]);
+        assert!(game.slots_loaded.len() == 1 && game.slots_loaded[0].grid == saved.grid);
+
+        let mut watched = Game::new(&ui, game.start_board.clone().unwrap());
+        watched.animation_ms = 0;
+        watched.start_tiles = 0;
+        watched.replay_slots = Some(game.slots_loaded.iter().cloned().collect());
+        watched.start();
+        for &key in &game.keys_played {
+            watched.step(key);
+        }
+        assert!(watched.board.grid == game.board.grid);
+    }
+
+    #[test]
+    fn loaded_slot_keeps_its_handicap() {
+        let ui = NullUI::new(::std::iter::empty());
+        let mut saved = Board::from_text("2 4 8 16\n0 0 0 0\n0 0 0 0\n0 0 0 0", BoardSize::default(), 1).unwrap();
+        saved.start_score = 100;
+        saved.score = 150;
+        let mut game = game_on(&ui, CORNER);
+        game.replay_slots = Some(vec![saved].into_iter().collect());
+        game.step(Key::Char('<'));
+        assert_eq!(game.board.start_score, 100);
+        assert_eq!(game.board.earned(), 50);
+        game.step(Key::Char('u'));
+        assert_eq!((game.board.score, game.board.start_score), (0, 0));
+    }
+
+    #[test]
+    fn diagonal_keys_need_diagonals() {
+        let ui = NullUI::new(::std::iter::empty());
+        let mut game = game_on(&ui, "0 0 0 0\n0 0 0 0\n0 0 0 0\n2 0 0 0");
+        let before = game.board.clone();
+        game.step(Direction::UpRight.key());
+        assert_eq!(game.moves, 0);
+        assert!(game.board.grid == before.grid);
+
+        game.board.diagonals = true;
+        game.step(Direction::UpRight.key());
+        assert_eq!(game.moves, 1);
+        assert_eq!(game.board.grid[3][0].value(), 2);
+    }
+}
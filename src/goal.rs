@@ -0,0 +1,88 @@
+//! Extra ways to win, for custom challenges
+
+use board::Board;
+
+/// A condition checked after every move, besides reaching the win target
+pub trait Goal {
+    /// Whether the game is won after `moves` moves
+    fn reached(&self, board: &Board, moves: usize) -> bool;
+    /// Whether the goal is out of reach, which loses the game
+    fn failed(&self, board: &Board, moves: usize) -> bool;
+    /// How far along the game is, for the stats line
+    fn progress(&self, board: &Board, moves: usize) -> String;
+}
+
+/// Reach a score, within a number of moves if there is a budget; a starting
+/// score doesn't count towards it
+pub struct ScoreGoal {
+    pub score: usize,
+    pub budget: Option<usize>,
+}
+
+/// Keep playing for a number of moves
+pub struct MovesGoal {
+    pub moves: usize,
+}
+
+impl Goal for ScoreGoal {
+    fn reached(&self, board: &Board, _moves: usize) -> bool {
+        board.earned() >= self.score
+    }
+
+    fn failed(&self, board: &Board, moves: usize) -> bool {
+        match self.budget {
+            Some(budget) => moves >= budget && !self.reached(board, moves),
+            None => false,
+        }
+    }
+
+    fn progress(&self, board: &Board, moves: usize) -> String {
+        match self.budget {
+            Some(budget) => format!("Goal: {}/{} in {}/{} moves", board.earned(), self.score, moves, budget),
+            None => format!("Goal: {}/{}", board.earned(), self.score),
+        }
+    }
+}
+
+impl Goal for MovesGoal {
+    fn reached(&self, _board: &Board, moves: usize) -> bool {
+        moves >= self.moves
+    }
+
+    fn failed(&self, _board: &Board, _moves: usize) -> bool {
+        false
+    }
+
+    fn progress(&self, _board: &Board, moves: usize) -> String {
+        format!("Goal: {}/{} moves", moves, self.moves)
+    }
+}
+
+/// The goal set by --goal-score and --goal-moves, which together give a score
+/// to reach within a number of moves
+pub fn goal_from_flags(score: Option<usize>, moves: Option<usize>) -> Option<Box<Goal>> {
+    match (score, moves) {
+        (Some(score), budget) => Some(Box::new(ScoreGoal { score: score, budget: budget })),
+        (None, Some(moves)) => Some(Box::new(MovesGoal { moves: moves })),
+        (None, None) => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use board::BoardSize;
+
+    #[test]
+    fn start_score_does_not_count_towards_the_goal() {
+        let goal = ScoreGoal { score: 100, budget: Some(10) };
+        let mut board = Board::new(BoardSize::default(), 1);
+        board.start_score = 500;
+        board.score = 560;
+        assert!(!goal.reached(&board, 3));
+        assert!(goal.failed(&board, 10));
+        assert_eq!(goal.progress(&board, 3), "Goal: 60/100 in 3/10 moves");
+        board.score = 600;
+        assert!(goal.reached(&board, 4));
+    }
+}
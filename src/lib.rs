@@ -0,0 +1,48 @@
+//! The game logic of 2048a, without a terminal.
+//!
+//! A `Board` holds the tiles and the rules for moving them. A `Game` wraps a
+//! board with what a session needs, such as undo history, the clock and the
+//! animation, and takes its input through the `UI` trait. Anything that can
+//! wait for keys and draw can play; `NullUI` does neither and suits tests and
+//! scripted play:
+//!
+//! ```no_run
+//! use game2048::{Board, BoardSize, Game, Key, NullUI};
+//!
+//! let ui = NullUI::new(vec![Key::Left, Key::Up].into_iter());
+//! let mut game = Game::new(&ui, Board::new(BoardSize::default(), 1));
+//! game.start();
+//! let result = game.step(Key::Left);
+//! println!("{:?}", result);
+//! ```
+//!
+//! A game leaves the player's files alone: it only reads and writes a best
+//! score once `Game::set_profile` asks it to.
+
+extern crate rand;
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+extern crate serde_json;
+
+pub mod batch;
+pub mod board;
+pub mod daily;
+pub mod events;
+pub mod game;
+pub mod goal;
+pub mod profile;
+pub mod race;
+pub mod replay;
+pub mod record;
+pub mod scenario;
+pub mod solver;
+pub mod stats;
+pub mod ui;
+
+pub use events::{EventSink, JsonLinesSink};
+pub use board::{Appearing, Board, BoardSize, Direction, Movement, Point, ScoringRule, SpawnPolicy, State,
+                Tile};
+pub use game::{Easing, Game, StepResult, Summary};
+pub use stats::{Outcome, StatsLog};
+pub use ui::{abbreviate, Key, NullUI, RecordingUI, UI};
@@ -1,647 +1,866 @@
 // Implements http://rosettacode.org/wiki/2048
 //
 // Based on the C++ version: http://rosettacode.org/wiki/2048#C.2B.2B
-// Uses rustbox (termbox) to draw the board.
+// Uses rustbox (termbox) to draw the board, the game itself is in the library.
 
-extern crate rustbox;
+extern crate game2048;
 extern crate rand;
-
-use std::fmt;
+#[cfg(feature = "termbox")]
+extern crate rustbox;
+#[cfg(feature = "termbox")]
+extern crate serde;
+#[cfg(feature = "termbox")]
+#[macro_use]
+extern crate serde_derive;
+#[cfg(feature = "termbox")]
+extern crate toml;
+
+#[cfg(feature = "termbox")]
+mod termbox;
+
+use std::collections::{HashSet, VecDeque};
+use std::fs;
+use std::io::{self, Read};
+use std::path::PathBuf;
 use std::time;
 
-use rand::distributions::{IndependentSample, Range};
-use rustbox::{Color, RustBox};
-use rustbox::Key as RKey;
-
-const NCOLS: usize = 4;
-const NROWS: usize = 4;
-const CELL_WIDTH: usize = 6;
-const CELL_HEIGHT: usize = 3;
-const BOARD_WIDTH: usize = 2 + (CELL_WIDTH + 2) * NCOLS;
-const BOARD_HEIGHT: usize = 1 + (CELL_HEIGHT + 1) * NROWS;
-
-
-#[derive(PartialEq, Clone, Copy)]
-enum Direction {
-    Up,
-    Down,
-    Left,
-    Right,
-}
-
-impl Direction {
-    fn offset(self) -> (i32, i32) {
-        match self {
-            Direction::Up => (0, -1),
-            Direction::Down => (0, 1),
-            Direction::Left => (-1, 0),
-            Direction::Right => (1, 0),
-        }
-    }
-}
-
-#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
-pub enum Key {
-    Right,
-    Left,
-    Up,
-    Down,
-    Char(char),
-}
-
-trait UI {
-    fn wait_key(&self, Option<u64>) -> Option<Key>;
-    fn draw_bg(&self, x_offset: usize, y_offset: usize);
-    fn draw_grid(&self, grid: [[Tile; NROWS]; NCOLS]);
-    fn draw_tile(&self, col: usize, row: usize, tile: Tile, partial: Option<f32>);
-    fn draw_tile_at(&self, tile: Tile, x_coord: usize, y_coord: usize, partial: Option<f32>);
-    fn present(&self);
-    fn draw_lost(&self);
-    fn draw_won(&self);
-    fn draw_score(&self, text: String);
-    fn draw_instructions(&self, text: String);
-}
-
-struct TermboxUI<'a> {
-    rustbox: &'a RustBox,
-    board: [[Color; BOARD_HEIGHT]; BOARD_WIDTH],
-}
-
-impl<'a> UI for TermboxUI<'a> {
-    fn wait_key(&self, timeout: Option<u64>) -> Option<Key> {
-        let event = match timeout {
-            Some(time) => self.rustbox.peek_event(std::time::Duration::from_millis(time), false),
-            None => self.rustbox.poll_event(false),
-        };
-        match event {
-            Ok(rustbox::Event::KeyEvent(key)) => {
-                match key {
-                    RKey::Char('q') => Some(Key::Char('q')),
-                    RKey::Up => Some(Key::Up),
-                    RKey::Down => Some(Key::Down),
-                    RKey::Left => Some(Key::Left),
-                    RKey::Right => Some(Key::Right),
-                    _ => None,
-                }
-            }
-            Err(e) => panic!("{}", e),
-            _ => None,
-        }
-    }
-
-    fn draw_bg(&self, x_offset: usize, y_offset: usize) {
-        for x in 0 .. BOARD_WIDTH {
-            for y in 0 .. BOARD_HEIGHT {
-                let color = self.board[x][y];
-                self.rustbox.print_char(x + x_offset,
-                                   y + y_offset,
-                                   rustbox::RB_NORMAL,
-                                   color,
-                                   color,
-                                   ' ');
+use game2048::{Board, BoardSize, Direction, Easing, EventSink, Game, JsonLinesSink, Key, NullUI, ScoringRule,
+               SpawnPolicy, State, StatsLog, UI};
+use game2048::batch::run_batch;
+use game2048::daily::{daily_seed, utc_date};
+use game2048::game::DEFAULT_FPS;
+use game2048::goal::goal_from_flags;
+use game2048::profile::{is_valid_profile, DEFAULT_PROFILE};
+use game2048::race::Race;
+use game2048::record::{key_name, parse_moves, rules, Recording};
+use game2048::scenario::run_scenario;
+use game2048::solver::{strategy_by_name, Strategy, DEFAULT_DEPTH};
+
+/// Bounds of --fps: fewer frames make a slide look like a jump, and terminals
+/// don't keep up with many more
+const MIN_FPS: u64 = 5;
+const MAX_FPS: u64 = 120;
+
+/// Plays without a terminal and prints the result, the moves come from the
+/// strategy if there is one, then from the game's script, then from stdin
+fn run_headless(game: &mut Game, strategy: Option<Box<Strategy>>, dump_json: bool) -> Result<(), String> {
+    game.animation_ms = 0;
+    game.start();
+    game.finish_animation();
+
+    if strategy.is_none() && game.script.is_empty() {
+        let mut input = String::new();
+        io::stdin().read_to_string(&mut input).map_err(|e| e.to_string())?;
+        game.script.extend(parse_moves(&input)?);
+    }
+
+    loop {
+        let key = match strategy {
+            Some(ref strategy) if game.board.state == State::Playing => {
+                strategy.choose(&game.board).map(Direction::key)
             }
+            Some(_) => None,
+            None => game.script.pop_front(),
+        };
+        match key {
+            Some(key) => game.step(key),
+            None => break,
+        };
+        if dump_json {
+            println!("{}", game.board.to_json());
         }
     }
 
-    fn draw_grid(&self, grid: [[Tile; NROWS]; NCOLS]) {
-        for x in 0.. NCOLS {
-            for y in 0.. NROWS {
-                self.draw_tile(x, y, grid[x][y], None)
-            }
+    game.quit();
+    println!("Score: {}", game.board.score);
+    if game.hotseat {
+        println!("P1: {}  P2: {}", game.player_scores[0], game.player_scores[1]);
+        match game.hotseat_loser() {
+            Some(player) => println!("Player {} wins", 2 - player),
+            None if game.board.state != State::Playing => println!("A draw"),
+            None => {}
         }
     }
-
-    fn draw_tile(&self, col: usize, row: usize, tile: Tile, partial: Option<f32>) {
-        let x_offset = 2;
-        let y_offset = 3;
-
-        let x_coord = x_offset + col * CELL_WIDTH + col * 2;
-        let y_coord = y_offset + row * CELL_HEIGHT + row;
-
-        self.draw_tile_at(tile, x_coord, y_coord, partial);
+    if let Some(percent) = game.optimal_percent() {
+        println!("Optimal moves: {}%", percent);
     }
+    print!("{}", game.board);
+    Ok(())
+}
 
-    fn draw_tile_at(&self, tile: Tile, x_coord: usize, y_coord: usize, partial: Option<f32>) {
-        let x_text_offset = (CELL_WIDTH as f64 / 2 as f64).floor() as usize;
-        let y_text_offset = (CELL_HEIGHT as f64 / 2 as f64).floor() as usize;
-        let x_centre = x_coord + x_text_offset;
-        let y_centre = y_coord + y_text_offset;
-
-        let num: String = format!("{}", tile);
-        let x_text_pos = x_centre - num.len() / 2;
-        let tile_colour = match num.as_ref() {
-            "2" => Color::Byte(224),
-            "4" => Color::Byte(222),
-            "8" => Color::Byte(216),
-            "16" => Color::Byte(209),
-            "32" => Color::Byte(202),
-            "64" => Color::Byte(203),
-            "128" => Color::Byte(230),
-            "256" => Color::Byte(226),
-            "512" => Color::Byte(193),
-            "1024" => Color::Byte(190),
-            "2048" => Color::Byte(214),
-            _ => Color::Black,
+/// Plays the game's script, a replay, and checks the score and tiles after
+/// every key against those recorded with it; returns how many were checked
+fn verify_replay(game: &mut Game, expected: &[Option<(usize, String)>]) -> Result<usize, String> {
+    game.animation_ms = 0;
+    game.start();
+    game.finish_animation();
+
+    let mut checked = 0;
+    for (number, expected) in expected.iter().enumerate() {
+        let key = match game.script.pop_front() {
+            Some(key) => key,
+            None => break,
         };
-        if num != "0" {
-            if let Some(ratio) = partial {
-                for column in 0 .. CELL_WIDTH {
-                    for row in 0 .. CELL_HEIGHT {
-                        let x = x_coord + column;
-                        let y = y_coord + row;
-                        if (x as f32 - x_centre as f32).abs() < CELL_WIDTH as f32 * ratio / 2.0
-                            && (y as f32 - y_centre as f32).abs() < CELL_HEIGHT as f32 * ratio / 2.0 {
-                            self.rustbox.print_char(x, y,
-                                                    rustbox::RB_NORMAL,
-                                                    tile_colour,
-                                                    tile_colour, ' ');
-                        }
-                    }
-                }
-            } else {
-                self.draw_rectangle(x_coord,
-                                    y_coord,
-                                    CELL_WIDTH,
-                                    CELL_HEIGHT,
-                                    tile_colour,
-                );
+        game.step(key);
+        if let Some((score, ref tiles)) = *expected {
+            let found = game.board.to_code();
+            if game.board.score != score || found != *tiles {
+                return Err(format!("step {} ({}): expected score {} and tiles {}, got {} and {}",
+                                   number + 1, key_name(key), score, tiles, game.board.score, found));
             }
-            self.rustbox.print(x_text_pos,
-                               y_centre,
-                               rustbox::RB_NORMAL,
-                               Color::Byte(232),
-                               tile_colour,
-                               &num);
+            checked += 1;
         }
     }
-
-    fn present(&self) {
-        self.rustbox.present();
-    }
-
-    fn draw_lost(&self) {
-        self.draw_text(16, 12, "You lost!".to_string(), Color::Red, Color::Black);
-    }
-
-    fn draw_won(&self) {
-        self.draw_text(16, 12, "You won!".to_string(), Color::Green, Color::Black);
+    if checked == 0 {
+        return Err("nothing to verify, the recording has no scores or tiles".to_string());
     }
+    Ok(checked)
+}
 
-    fn draw_score(&self, text: String) {
-        self.draw_text(13, 1, text, Color::White, Color::Black);
-    }
+/// Sets the rules of play given on the command line
+fn apply_rules(board: &mut Board, options: &Options) {
+    board.win_target = options.target;
+    board.four_prob = options.four_prob;
+    board.gravity = options.gravity;
+    board.scoring = options.scoring;
+    board.spawn = options.spawn;
+    board.wrap = options.wrap;
+    board.diagonals = options.diagonals;
+}
 
-    fn draw_instructions(&self, text: String) {
-        self.draw_text(11, 19, text, Color::White, Color::Black);
-    }
+/// An empty board with the rules on the command line, its score starting
+/// from the handicap; a loaded board keeps the score it comes with
+fn new_board(size: BoardSize, seed: u64, options: &Options) -> Board {
+    let mut board = Board::new(size, seed);
+    apply_rules(&mut board, options);
+    board.start_score = options.start_score;
+    board.score = options.start_score;
+    board
 }
 
-impl<'a> TermboxUI<'a> {
-    fn new(rustbox: &'a rustbox::RustBox) -> TermboxUI<'a> {
+/// Reads a starting position written as `Board::from_text` expects
+fn load_board(path: &str, size: BoardSize, seed: u64) -> Result<Board, String> {
+    let text = fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+    Board::from_text(&text, size, seed).map_err(|e| format!("{}: {}", path, e))
+}
 
-        let mut board = [[Color::Byte(137); BOARD_HEIGHT]; BOARD_WIDTH];
+/// Settings taken from the command line
+struct Options {
+    size: BoardSize,
+    seed: Option<u64>,
+    anim_ms: u32,
+    headless: bool,
+    dump_json: bool,
+    strategy: Option<String>,
+    target: usize,
+    /// Name of the theme, see `Theme::by_name`
+    theme: String,
+    /// 16 or 256, detected from the terminal when not given
+    colors: Option<u16>,
+    record: Option<String>,
+    replay: Option<String>,
+    four_prob: f64,
+    /// Config file given on the command line
+    config: Option<PathBuf>,
+    hints: bool,
+    /// Seed from today's date
+    daily: bool,
+    /// No colours at all
+    no_color: bool,
+    bell: bool,
+    bell_merge: usize,
+    /// Seconds of play allowed
+    blitz: Option<u64>,
+    /// Directions a drill allows, all of them when None
+    only: Option<HashSet<Direction>>,
+    /// File with the starting position
+    load: Option<String>,
+    debug_coords: bool,
+    /// Where events go as JSON lines, - for stdout
+    events: Option<String>,
+    start_tiles: usize,
+    /// Handicap the score starts from
+    start_score: usize,
+    /// CSV file that gets a line after every game
+    stats_file: Option<String>,
+    /// Score that wins the game
+    goal_score: Option<usize>,
+    /// Moves that win the game, or the budget for reaching goal_score
+    goal_moves: Option<usize>,
+    zen: bool,
+    show_next: bool,
+    /// Mark tiles with their power of two as well as their colour
+    labels: bool,
+    /// Cells a line high, for narrow terminals
+    compact: bool,
+    /// Big tiles in thousands, such as 16k
+    abbrev: bool,
+    /// Drags and the mouse wheel make moves
+    mouse: bool,
+    /// Compare every move with the solver's
+    analyze: bool,
+    /// Tab shows what a move would leave
+    preview: bool,
+    /// Two players take turns
+    hotseat: bool,
+    /// Two players race on boards of their own
+    race: bool,
+    /// Time between keys when watching a game again
+    watch_ms: u64,
+    /// Most animation frames a second
+    fps: u64,
+    /// How sliding tiles speed up and slow down
+    easing: Easing,
+    /// Check the replay against the scores and tiles recorded with it
+    verify: bool,
+    /// Games the strategy plays for a table of how they went
+    batch: Option<usize>,
+    /// Scenario file to set up, play and check, see `scenario`
+    scenario: Option<String>,
+    /// Moves the searching strategies look ahead
+    depth: usize,
+    debounce_ms: u64,
+    /// Undos allowed in each game
+    undos: Option<usize>,
+    /// Shuffles allowed in each game
+    shuffles: usize,
+    /// Tiles that can be removed in each game
+    removals: usize,
+    gravity: bool,
+    /// Show the score in the terminal's title
+    set_title: bool,
+    /// What each merge scores
+    scoring: ScoringRule,
+    /// Where new tiles may appear
+    spawn: SpawnPolicy,
+    /// Opposite edges of the board are joined
+    wrap: bool,
+    /// Tiles can move diagonally too
+    diagonals: bool,
+    /// Colour numbers of the background and of empty cells, instead of the theme's
+    bg: Option<u16>,
+    empty: Option<u16>,
+    /// Whose high score and key bindings to use
+    profile: String,
+    /// Key that suggests a move, instead of ?
+    hint_key: Option<char>,
+}
 
-        for i in 0..NCOLS {
-            for j in 0..NROWS {
-                let left = 2 + i * (CELL_WIDTH + 2);
-                let top = 1 + j * (CELL_HEIGHT + 1);
-                if left + CELL_WIDTH < BOARD_WIDTH && top + CELL_HEIGHT < BOARD_HEIGHT {
-                    for x in left .. left + CELL_WIDTH {
-                        for y in top .. top + CELL_HEIGHT{
-                            board[x][y] = Color::Byte(180);
-                        }
+impl Options {
+    fn from_args<I: Iterator<Item = String>>(mut args: I) -> Result<Options, String> {
+        let mut options = Options {
+            size: BoardSize::default(),
+            seed: None,
+            anim_ms: 500,
+            headless: false,
+            dump_json: false,
+            strategy: None,
+            target: 2048,
+            theme: "classic".to_string(),
+            colors: None,
+            record: None,
+            replay: None,
+            four_prob: 0.1,
+            config: None,
+            hints: false,
+            daily: false,
+            no_color: false,
+            bell: false,
+            bell_merge: 512,
+            blitz: None,
+            only: None,
+            load: None,
+            debug_coords: false,
+            events: None,
+            start_tiles: 2,
+            start_score: 0,
+            stats_file: None,
+            goal_score: None,
+            goal_moves: None,
+            zen: false,
+            show_next: false,
+            labels: false,
+            compact: false,
+            abbrev: false,
+            mouse: false,
+            analyze: false,
+            preview: false,
+            hotseat: false,
+            race: false,
+            watch_ms: 200,
+            fps: DEFAULT_FPS,
+            easing: Easing::Linear,
+            verify: false,
+            batch: None,
+            scenario: None,
+            depth: DEFAULT_DEPTH,
+            debounce_ms: 0,
+            undos: None,
+            shuffles: 0,
+            removals: 0,
+            gravity: false,
+            set_title: false,
+            scoring: ScoringRule::Classic,
+            spawn: SpawnPolicy::Uniform,
+            wrap: false,
+            diagonals: false,
+            bg: None,
+            empty: None,
+            profile: DEFAULT_PROFILE.to_string(),
+            hint_key: None,
+        };
+        while let Some(arg) = args.next() {
+            match arg.as_ref() {
+                "--cols" => options.size.cols = parse_value(&arg, args.next())?,
+                "--rows" => options.size.rows = parse_value(&arg, args.next())?,
+                "--seed" => options.seed = Some(parse_value(&arg, args.next())?),
+                "--anim-ms" => options.anim_ms = parse_value(&arg, args.next())?,
+                "--debounce-ms" => options.debounce_ms = parse_value(&arg, args.next())?,
+                "--headless" => options.headless = true,
+                "--dump-json" => options.dump_json = true,
+                "--target" => {
+                    options.target = parse_value(&arg, args.next())?;
+                    if options.target < 4 || !options.target.is_power_of_two() {
+                        return Err(format!("the target must be a power of two of at least 4, not {}",
+                                           options.target));
+                    }
+                }
+                "--theme" => {
+                    let name: String = parse_value(&arg, args.next())?;
+                    if !theme_exists(&name) {
+                        return Err(format!("unknown theme: {}", name));
                     }
+                    options.theme = name;
+                }
+                "--no-color" => options.no_color = true,
+                "--bg" => options.bg = Some(parse_colour(&arg, args.next())?),
+                "--empty" => options.empty = Some(parse_colour(&arg, args.next())?),
+                "--color" => {
+                    let colors = parse_value(&arg, args.next())?;
+                    if colors != 16 && colors != 256 {
+                        return Err(format!("--color takes 16 or 256, not {}", colors));
+                    }
+                    options.colors = Some(colors);
+                }
+                "--record" => options.record = Some(parse_value(&arg, args.next())?),
+                "--replay" => options.replay = Some(parse_value(&arg, args.next())?),
+                "--verify-replay" => {
+                    options.replay = Some(parse_value(&arg, args.next())?);
+                    options.verify = true;
+                    options.headless = true;
                 }
+                "--events" => options.events = Some(parse_value(&arg, args.next())?),
+                "--goal-score" => options.goal_score = Some(parse_value(&arg, args.next())?),
+                "--goal-moves" => options.goal_moves = Some(parse_value(&arg, args.next())?),
+                "--stats-file" => options.stats_file = Some(parse_value(&arg, args.next())?),
+                "--start-tiles" => options.start_tiles = parse_value(&arg, args.next())?,
+                "--start-score" => options.start_score = parse_value(&arg, args.next())?,
+                "--load" => options.load = Some(parse_value(&arg, args.next())?),
+                "--four-prob" => {
+                    options.four_prob = parse_value(&arg, args.next())?;
+                    if !(options.four_prob >= 0.0 && options.four_prob <= 1.0) {
+                        return Err(format!("--four-prob must be between 0.0 and 1.0, not {}",
+                                           options.four_prob));
+                    }
+                }
+                "--profile" => {
+                    let name: String = parse_value(&arg, args.next())?;
+                    if !is_valid_profile(&name) {
+                        return Err(format!("invalid profile name: {}, use up to 32 letters, digits, - and _", name));
+                    }
+                    options.profile = name;
+                }
+                "--config" => {
+                    let path: String = parse_value(&arg, args.next())?;
+                    options.config = Some(PathBuf::from(path));
+                }
+                "--hints" => options.hints = true,
+                "--undos" => options.undos = Some(parse_value(&arg, args.next())?),
+                "--shuffles" => options.shuffles = parse_value(&arg, args.next())?,
+                "--removals" => options.removals = parse_value(&arg, args.next())?,
+                "--zen" => options.zen = true,
+                "--gravity" => options.gravity = true,
+                "--wrap" => options.wrap = true,
+                "--diagonals" => options.diagonals = true,
+                "--set-title" => options.set_title = true,
+                "--spawn" => {
+                    let name: String = parse_value(&arg, args.next())?;
+                    options.spawn = match SpawnPolicy::from_name(&name) {
+                        Some(policy) => policy,
+                        None => return Err(format!("unknown spawn policy: {}", name)),
+                    };
+                }
+                "--scoring" => {
+                    let name: String = parse_value(&arg, args.next())?;
+                    options.scoring = match ScoringRule::from_name(&name) {
+                        Some(rule) => rule,
+                        None => return Err(format!("unknown scoring rule: {}", name)),
+                    };
+                }
+                "--show-next" => options.show_next = true,
+                "--labels" => options.labels = true,
+                "--compact" => options.compact = true,
+                "--abbrev" => options.abbrev = true,
+                "--mouse" => options.mouse = true,
+                "--analyze" => options.analyze = true,
+                "--preview" => options.preview = true,
+                "--hotseat" => options.hotseat = true,
+                "--race" => options.race = true,
+                "--script" => {
+                    options.scenario = Some(parse_value(&arg, args.next())?);
+                    options.headless = true;
+                }
+                "--batch" => {
+                    let games = parse_value(&arg, args.next())?;
+                    if games == 0 {
+                        return Err("--batch needs at least 1 game".to_string());
+                    }
+                    options.batch = Some(games);
+                    options.headless = true;
+                }
+                "--fps" => {
+                    options.fps = parse_value(&arg, args.next())?;
+                    if options.fps < MIN_FPS || options.fps > MAX_FPS {
+                        return Err(format!("--fps must be between {} and {}, not {}", MIN_FPS, MAX_FPS, options.fps));
+                    }
+                }
+                "--easing" => {
+                    let name: String = parse_value(&arg, args.next())?;
+                    options.easing = match Easing::from_name(&name) {
+                        Some(easing) => easing,
+                        None => return Err(format!("unknown easing: {}", name)),
+                    };
+                }
+                "--watch-ms" => options.watch_ms = parse_value(&arg, args.next())?,
+                "--hint-key" => options.hint_key = Some(parse_value(&arg, args.next())?),
+                "--debug-coords" => options.debug_coords = true,
+                "--blitz" => {
+                    let secs = parse_value(&arg, args.next())?;
+                    if secs == 0 {
+                        return Err("--blitz needs at least 1 second".to_string());
+                    }
+                    options.blitz = Some(secs);
+                }
+                "--only" => {
+                    let list: String = parse_value(&arg, args.next())?;
+                    let mut only = HashSet::new();
+                    for name in list.split(',') {
+                        match Direction::from_name(name.trim()) {
+                            Some(direc) => only.insert(direc),
+                            None => return Err(format!("unknown direction: {}", name)),
+                        };
+                    }
+                    options.only = Some(only);
+                }
+                "--bell" => options.bell = true,
+                "--bell-merge" => {
+                    options.bell = true;
+                    options.bell_merge = parse_value(&arg, args.next())?;
+                }
+                "--daily" => options.daily = true,
+                "--auto" => options.strategy = Some("greedy".to_string()),
+                "--depth" => options.depth = parse_value(&arg, args.next())?,
+                "--strategy" => {
+                    let name: String = parse_value(&arg, args.next())?;
+                    if strategy_by_name(&name, DEFAULT_DEPTH).is_none() {
+                        return Err(format!("unknown strategy: {}", name));
+                    }
+                    options.strategy = Some(name);
+                }
+                _ => return Err(format!("unknown argument: {}", arg)),
             }
         }
-        TermboxUI {
-            rustbox: rustbox,
-            board: board,
+        if options.load.is_some() && (options.record.is_some() || options.replay.is_some()) {
+            // recordings only know the seed and the size, not the starting tiles
+            return Err("--load can't be used with --record or --replay".to_string());
         }
-    }
-
-    fn fill_area(&self, x: usize, y: usize, w: usize, h: usize, fg: Color, bg: Color) {
-        for row in 0..h {
-            for column in 0..w {
-                self.rustbox.print_char(x + column, y + row, rustbox::RB_NORMAL, fg, bg, ' ');
-            }
+        if options.race && (options.headless || options.hotseat || options.strategy.is_some()
+                             || options.record.is_some() || options.replay.is_some()) {
+            return Err("--race can't be used with --headless, --hotseat, --strategy, --record or --replay"
+                       .to_string());
         }
-    }
-
-    fn draw_rectangle(&self,
-                      x: usize,
-                      y: usize,
-                      w: usize,
-                      h: usize,
-                      fill: Color,
-    ) {
-        self.fill_area(x, y, w, h, fill, fill);
-    }
-
-    fn draw_text(&self, x: usize, y: usize, line: String, fg: Color, bg: Color) -> (usize, usize) {
-        for (i, ch) in line.chars().enumerate() {
-            self.rustbox.print_char(x + i, y, rustbox::RB_NORMAL, fg, bg, ch);
+        if options.scenario.is_some() && (options.strategy.is_some() || options.race
+                                          || options.replay.is_some() || options.batch.is_some()
+                                          || options.record.is_some() || options.stats_file.is_some()) {
+            return Err("--script can't be used with --strategy, --race, --replay, --batch, --record or --stats-file"
+                       .to_string());
         }
-        (x + line.len(), y)
-    }
-}
-
-#[derive(Copy, Clone)]
-struct Tile {
-    _value: usize,
-    _value_old: usize,
-    _blocked: bool,
-    /// the tile changed, but the old value should be shown before animation is done
-    _pending: bool,
-}
-
-impl Tile {
-    fn new() -> Tile {
-        Tile {
-            _value: 0,
-            _value_old: 0,
-            _blocked: false,
-            _pending: false,
+        if options.verify && (options.record.is_some() || options.stats_file.is_some()) {
+            return Err("--verify-replay can't be used with --record or --stats-file".to_string());
         }
-    }
-
-    fn from_value(value: usize) -> Tile {
-        Tile {
-            _value: value,
-            _value_old: 0,
-            _blocked: false,
-            _pending: false,
+        if options.batch.is_some() && options.strategy.is_none() {
+            return Err("--batch needs a --strategy to play the games".to_string());
         }
-    }
-
-    fn set(&mut self, val: usize) {
-        self._value_old = self._value;
-        self._value = val;
-    }
-
-    fn get(&self) -> usize {
-        if self._pending {
-            self._value_old
-        } else {
-            self._value
+        if options.batch.is_some() && (options.load.is_some() || options.hotseat || options.race
+                                       || options.record.is_some() || options.replay.is_some()
+                                       || options.stats_file.is_some() || options.events.is_some()) {
+            return Err("--batch can't be used with --load, --hotseat, --race, --record, --replay, --stats-file \
+                        or --events".to_string());
         }
-    }
-
-    fn is_empty(&self) -> bool {
-        self._value == 0
-    }
-
-    fn blocked(&mut self, b: bool) {
-        self._blocked = b;
-    }
-
-    fn is_blocked(&self) -> bool {
-        return self._blocked;
-    }
-
-    fn set_pending(&mut self, pending: bool) {
-        self._pending = pending;
-    }
-}
-
-impl fmt::Display for Tile {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{}", self.get())
-    }
-}
-
-impl PartialEq for Tile {
-    fn eq(&self, other: &Tile) -> bool {
-        self._value == other._value
-    }
-
-    fn ne(&self, other: &Tile) -> bool {
-        self._value != other._value
+        if options.daily && options.seed.is_some() {
+            return Err("--daily and --seed can't be used together".to_string());
+        }
+        if options.size.cols < 2 || options.size.rows < 2 {
+            return Err("the board needs at least 2 columns and 2 rows".to_string());
+        }
+        if options.zen && options.blitz.is_some() {
+            return Err("--zen and --blitz can't be used together".to_string());
+        }
+        if options.zen && options.goal_score.is_some() && options.goal_moves.is_some() {
+            // a move budget can lose the game
+            return Err("--zen can't be used with a move budget".to_string());
+        }
+        if options.goal_score == Some(0) || options.goal_moves == Some(0) {
+            return Err("goals must be above 0".to_string());
+        }
+        if options.depth == 0 {
+            return Err("--depth must be at least 1".to_string());
+        }
+        let cells = options.size.cols * options.size.rows;
+        if options.start_tiles < 1 || options.start_tiles >= cells {
+            return Err(format!("--start-tiles must be between 1 and {}", cells - 1));
+        }
+        Ok(options)
     }
 }
 
-#[derive(PartialEq, Debug)]
-enum State {
-    Playing,
-    Won,
-    Lost,
+#[cfg(feature = "termbox")]
+fn theme_exists(name: &str) -> bool {
+    termbox::Theme::by_name(name).is_some()
 }
 
-struct Point {
-    x: usize,
-    y: usize,
+/// Themes only matter in the terminal
+#[cfg(not(feature = "termbox"))]
+fn theme_exists(_name: &str) -> bool {
+    true
 }
 
-struct Movement {
-    tile: Tile,
-    pold: Point,
-    pnew: Point,
-}
-
-struct Appearing {
-    position: Point,
-    value: usize,
-}
-
-struct Game<'a> {
-    ui: &'a UI,
-    grid: [[Tile; NROWS]; NCOLS],
-    state: State,
-    score: usize,
-    moved: bool,
-    /// Vector containing tiles and their original position and destination
-    tiles_moving: Vec<Movement>,
-    /// where new tiles are appearing
-    points_appearing: Vec<Appearing>,
-    /// The time when the latest movement started
-    animation_start: time::Instant,
+/// Parses a colour number following a flag, one of the 256 a terminal may have
+fn parse_colour(flag: &str, value: Option<String>) -> Result<u16, String> {
+    let colour: u16 = parse_value(flag, value)?;
+    if colour > 255 {
+        return Err(format!("{} takes a colour from 0 to 255", flag));
+    }
+    Ok(colour)
 }
 
-impl<'a> Game<'a> {
-    fn new(ui: &'a UI) -> Game<'a> {
-        Game {
-            ui: ui,
-            grid: [[Tile::new(); NROWS]; NCOLS],
-            state: State::Playing,
-            score: 0,
-            moved: false,
-            tiles_moving: Vec::new(),
-            points_appearing: Vec::new(),
-            animation_start: time::Instant::now(),
-        }
+/// Parses the value following a flag
+fn parse_value<T: std::str::FromStr>(flag: &str, value: Option<String>) -> Result<T, String> {
+    match value {
+        Some(v) => v.parse().map_err(|_| format!("invalid value for {}: {}", flag, v)),
+        None => Err(format!("missing value for {}", flag)),
     }
+}
 
-    fn run(&mut self) {
-        self.ui.draw_instructions("←,↑,→,↓ or q".to_string());
-
-        for _ in 0..2 {
-            self.add_tile();
+fn main() {
+    let options = match Options::from_args(std::env::args().skip(1)) {
+        Ok(o) => o,
+        Err(e) => {
+            eprintln!("2048a: {}", e);
+            std::process::exit(2);
         }
-
-        loop {
-            self.draw();
-            self.moved = false;
-
-            let key = if self.tiles_moving.len() > 0
-                || self.points_appearing.len() > 0 {
-                // when there are tiles waiting to be moved, wait for a short time
-                self.ui.wait_key(Some(10))
-            } else {
-                self.ui.wait_key(None)
-            };
-
-            if key == Some(Key::Char('q')) {
-                break;
-            } else if key == None {
-                continue;
+    };
+    let replay = match options.replay {
+        Some(ref path) => match Recording::load(path) {
+            Ok(recording) => Some(recording),
+            Err(e) => {
+                eprintln!("2048a: {}", e);
+                std::process::exit(1);
             }
+        },
+        None => None,
+    };
 
-            // finish any on-going animation immediately
-            self.finish_animation();
-
-            // start moving
-            if self.state != State::Lost && self.state != State::Won {
-                if let Some(direc) = match key {
-                    Some(Key::Up) => Some(Direction::Up),
-                    Some(Key::Down) => Some(Direction::Down),
-                    Some(Key::Left) => Some(Direction::Left),
-                    Some(Key::Right) => Some(Direction::Right),
-                    _ => None,
-                } {
-                    self.move_all(direc);
+    let mut tag = None;
+    let seed = if options.daily {
+        let now = time::SystemTime::now().duration_since(time::UNIX_EPOCH).unwrap_or_default();
+        let (year, month, day) = utc_date(now.as_secs());
+        tag = Some(format!("Daily {}-{:02}-{:02}", year, month, day));
+        daily_seed(year, month, day)
+    } else if options.scenario.is_some() {
+        // a script's new tiles have to be the same every run for its asserts to hold
+        options.seed.unwrap_or(0)
+    } else {
+        options.seed.unwrap_or_else(rand::random)
+    };
+    let board = match replay {
+        Some(ref recording) => new_board(recording.size, recording.seed, &options),
+        None => match options.load {
+            Some(ref path) => match load_board(path, options.size, seed) {
+                Ok(mut board) => {
+                    apply_rules(&mut board, &options);
+                    board
                 }
-            }
-
-            for i in 0.. NCOLS {
-                for j in 0.. NROWS {
-                    self.grid[i][j].blocked(false);
+                Err(e) => {
+                    eprintln!("2048a: {}", e);
+                    std::process::exit(1);
                 }
+            },
+            None => new_board(options.size, seed, &options),
+        },
+    };
+    if let Some(ref recording) = replay {
+        let played = rules(&board, options.start_tiles);
+        match recording.rules {
+            Some(ref recorded) if *recorded != played => {
+                eprintln!("2048a: the recording was played with {}, not {}; give the same flags to replay it",
+                          recorded, played);
+                std::process::exit(1);
             }
-
-            if self.moved {
-                self.add_tile();
-            } else if !self.can_move() {
-                self.state = State::Lost;
-            }
-            self.animation_start = time::Instant::now();
+            _ => {}
         }
     }
 
-    fn add_tile(&mut self) {
-        let mut cantadd = true;
-        'OUTER: for i in 0.. NCOLS {
-            for j in 0.. NROWS {
-                if self.grid[i][j].is_empty() {
-                    cantadd = false;
-                    break 'OUTER;
-                }
+    let recorder = match options.record {
+        Some(ref path) => match Recording::create(path, &board, options.start_tiles) {
+            Ok(file) => Some(file),
+            Err(e) => {
+                eprintln!("2048a: {}: {}", path, e);
+                std::process::exit(1);
             }
-        }
-
-        let cantmove = !self.can_move();
-        if cantadd || cantmove {
-            return;
-        }
-
-        let between = Range::new(0f64, 1.);
-        let mut rng = rand::thread_rng();
-        let a = between.ind_sample(&mut rng);
+        },
+        None => None,
+    };
+    let expected = replay.as_ref().map(|r| r.expected.clone()).unwrap_or_default();
+    let script: VecDeque<Key> = replay.map(|r| r.keys.into_iter().collect()).unwrap_or_default();
+    let events: Option<Box<EventSink>> = match options.events.as_ref().map(String::as_str) {
+        Some("-") => Some(Box::new(JsonLinesSink::new(io::stdout()))),
+        Some(path) => match fs::OpenOptions::new().create(true).append(true).open(path) {
+            Ok(file) => Some(Box::new(JsonLinesSink::new(file))),
+            Err(e) => {
+                eprintln!("2048a: {}: {}", path, e);
+                std::process::exit(1);
+            }
+        },
+        None => None,
+    };
+    let stats = match options.stats_file {
+        Some(ref path) => match StatsLog::open(path) {
+            Ok(stats) => Some(stats),
+            Err(e) => {
+                eprintln!("2048a: {}: {}", path, e);
+                std::process::exit(1);
+            }
+        },
+        None => None,
+    };
 
-        let mut cell1 = rand::random::<(usize, usize)>();
-        while !self.grid[cell1.0 % NCOLS][cell1.1 % NROWS].is_empty() {
-            cell1 = rand::random::<(usize, usize)>();
-        }
-        self.points_appearing.push(Appearing {
-            value: if a > 0.9 { 4 } else { 2 },
-            position: Point { x: cell1.0 % NCOLS, y: cell1.1 % NROWS},
+    if let Some(games) = options.batch {
+        let strategy = options.strategy.as_ref()
+            .and_then(|name| strategy_by_name(name, options.depth))
+            .expect("--batch checks there is a strategy");
+        let results = run_batch(&*strategy, games, seed, |ui, seed| {
+            let board = new_board(options.size, seed, &options);
+            // the computer's games don't touch the player's best score
+            let mut game = Game::new(ui, board);
+            game.goal = goal_from_flags(options.goal_score, options.goal_moves);
+            game.start_tiles = options.start_tiles;
+            game
         });
-    }
-
-    fn can_move(&self) -> bool {
-        for i in 0..NCOLS {
-            for j in 0..NROWS {
-                if self.grid[i][j].is_empty() {
-                    return true;
+        print!("{}", results);
+        return;
+    }
+
+    if options.headless {
+        let ui = NullUI::new(std::iter::empty());
+        let mut game = headless_game(&ui, board, &options);
+        game.events = events;
+        if let Some(ref path) = options.scenario {
+            let result = fs::read_to_string(path)
+                .map_err(|e| e.to_string())
+                .and_then(|text| run_scenario(&mut game, &text));
+            match result {
+                Ok(checked) => println!("Script passed, {} assertions checked", checked),
+                Err(e) => {
+                    eprintln!("2048a: {}: {}", path, e);
+                    std::process::exit(1);
                 }
-
-                if self.test_add(i + 1, j, self.grid[i][j]) {
-                    return true;
-                };
-                if i > 0 && self.test_add(i - 1, j, self.grid[i][j]) {
-                    return true;
-                };
-                if self.test_add(i, j + 1, self.grid[i][j]) {
-                    return true;
-                };
-                if j > 0 && self.test_add(i, j - 1, self.grid[i][j]) {
-                    return true;
-                };
             }
+            return;
         }
-
-        return false;
-    }
-
-    fn test_add(&self, x: usize, y: usize, v: Tile) -> bool {
-        if x > 3 || y > 3 {
-            return false;
-        }
-        return self.grid[x][y] == v;
-    }
-
-    fn add_score(&mut self, score: usize) {
-        self.score += score;
-
-        if score == 2048 {
-            self.state = State::Won;
-        }
-    }
-
-    fn finish_animation(&mut self) {
-        for m in &self.tiles_moving {
-            self.grid[m.pnew.x][m.pnew.y].set_pending(false);
-        }
-        self.tiles_moving.truncate(0);
-
-        for a in &self.points_appearing {
-            self.grid[a.position.x][a.position.y].set(a.value);
-        }
-        self.points_appearing.truncate(0);
-    }
-
-    fn get_progress(&self) -> f32 {
-        // how much of the animation has been done
-        // duration of the entire animation in milliseconds
-        let animation_duration: u16 = 500;
-        let elapsed: u16 = self.animation_start.elapsed().as_secs() as u16 * 1000
-            + (self.animation_start.elapsed().subsec_nanos() / 1000000) as u16;
-        elapsed as f32 / animation_duration as f32
-    }
-
-    fn draw_moving(&mut self) {
-        let ratio = self.get_progress();
-        if ratio > 0.99 {
-            self.finish_animation();
+        game.script = script;
+        if options.verify {
+            match verify_replay(&mut game, &expected) {
+                Ok(checked) => println!("Replay verified, {} steps checked", checked),
+                Err(e) => {
+                    eprintln!("2048a: {}", e);
+                    std::process::exit(1);
+                }
+            }
             return;
         }
-        for m in &self.tiles_moving {
-            let col = m.pold.x as f32 + (m.pnew.x as f32 - m.pold.x as f32) * ratio;
-            let row = m.pold.y as f32 + (m.pnew.y as f32 - m.pold.y as f32) * ratio;
-
-            let x_offset = 2.0;
-            let y_offset = 3.0;
-
-            let x_now = x_offset + col * CELL_WIDTH as f32 + col * 2.0;
-            let y_now = y_offset + row * CELL_HEIGHT as f32 + row;
-
-            self.ui.draw_tile_at(m.tile, x_now as usize, y_now as usize, None);
+        game.recorder = recorder;
+        game.stats = stats;
+        if let Err(e) = run_headless(&mut game,
+                                    options.strategy.as_ref().and_then(|name| strategy_by_name(name, options.depth)),
+                                    options.dump_json) {
+            eprintln!("2048a: {}", e);
+            std::process::exit(1);
         }
-
-        for a in &self.points_appearing {
-            let x_offset = 2.0;
-            let y_offset = 3.0;
-            let col = a.position.x as f32;
-            let row = a.position.y as f32;
-
-            let x = x_offset + col * CELL_WIDTH as f32 + col * 2.0;
-            let y = y_offset + row * CELL_HEIGHT as f32 + row;
-
-            self.ui.draw_tile_at(Tile::from_value(a.value),
-                                 x as usize, y as usize,
-                                 Some(ratio));
+        return;
+    }
+
+    if let Err(e) = play_in_terminal(&options, board.size, |ui| {
+        let mut game = terminal_game(ui, board, &options);
+        game.analyze = options.analyze;
+        game.preview = options.preview;
+        game.hotseat = options.hotseat;
+        game.watch_ms = options.watch_ms;
+        game.strategy = options.strategy.as_ref().and_then(|name| strategy_by_name(name, options.depth));
+        // the best score is shown, but neither the computer's games nor
+        // replays of someone's recording replace it
+        game.keep_high_score = game.strategy.is_none() && options.replay.is_none();
+        game.recorder = recorder;
+        game.script = script;
+        game.debug_coords = options.debug_coords;
+        game.events = events;
+        game.stats = stats;
+        game.show_next = options.show_next;
+        game.tag = tag;
+        if options.race {
+            // the same tiles to start from, the stats and events follow player 1
+            let mut right = terminal_game(ui, game.board.clone(), &options);
+            game.tag = Some("Player 1".to_string());
+            game.instructions = Some("w, a, s, d, p, r or q".to_string());
+            right.tag = Some("Player 2".to_string());
+            right.instructions = Some("←,↑,→,↓, p, r or q".to_string());
+            Race::new(ui, game, right).run();
+        } else {
+            game.run();
         }
+    }) {
+        eprintln!("2048a: {}", e);
+        std::process::exit(1);
     }
+}
 
-    fn draw(&mut self) {
-        self.ui.draw_score(format!("Score: {}", self.score));
-        self.ui.draw_bg(0, 2);
-
-        self.draw_moving();
-
-        self.ui.draw_grid(self.grid);
-
-        if self.state == State::Lost {
-            self.ui.draw_lost();
-        } else if self.state == State::Won {
-            self.ui.draw_won();
-        }
-
-        self.ui.present();
+/// Sets the rules of a game given on the command line, see `apply_rules`
+/// for those of its board
+fn set_rules(game: &mut Game, options: &Options) {
+    game.goal = goal_from_flags(options.goal_score, options.goal_moves);
+    game.zen = options.zen;
+    game.undo_limit = options.undos;
+    game.undos_remaining = options.undos;
+    game.shuffle_limit = options.shuffles;
+    game.shuffles_left = options.shuffles;
+    game.removal_limit = options.removals;
+    game.removals_left = options.removals;
+    game.start_tiles = options.start_tiles;
+    if let Some(ref only) = options.only {
+        game.allowed_directions = only.clone();
     }
+}
 
-    fn move_direction(&mut self, x: usize, y: usize, d: Direction) -> (usize, usize) {
-        let (xd, yd) = d.clone().offset();
-
-        let xnew: i32 = x as i32 + xd;
-        let ynew: i32 = y as i32 + yd;
+/// A game to play without a terminal with the rules on the command line
+fn headless_game<'a>(ui: &'a UI, board: Board, options: &Options) -> Game<'a> {
+    let mut game = Game::new(ui, board);
+    set_rules(&mut game, options);
+    game.analyze = options.analyze;
+    game.hotseat = options.hotseat;
+    // the computer's games don't touch the player's best score, and neither
+    // does checking a script or a replay
+    if options.strategy.is_none() && options.scenario.is_none() && !options.verify {
+        game.set_profile(&options.profile);
+    }
+    game
+}
 
-        if ynew < 0 || ynew > (NROWS - 1) as i32 ||
-            xnew < 0 || xnew > (NCOLS - 1) as i32 {
-            return (x, y);
-        }
+/// A game to play in the terminal with the rules and settings on the command
+/// line, for each board of a race alike
+fn terminal_game<'a>(ui: &'a UI, board: Board, options: &Options) -> Game<'a> {
+    let mut game = Game::new(ui, board);
+    game.set_profile(&options.profile);
+    set_rules(&mut game, options);
+    game.animation_ms = options.anim_ms as u64;
+    game.fps = options.fps;
+    game.easing = options.easing;
+    game.hints = options.hints;
+    game.debounce_ms = options.debounce_ms;
+    game.bell = options.bell;
+    game.bell_merge = options.bell_merge;
+    game.time_limit = options.blitz.map(time::Duration::from_secs);
+    game
+}
 
-        let xnew: usize = xnew as usize;
-        let ynew: usize = ynew as usize;
-
-        let mut tilemoved = false;
-        if !self.grid[xnew][ynew].is_empty() && self.grid[xnew][ynew] == self.grid[x][y] &&
-            !self.grid[x][y].is_blocked() && !self.grid[xnew][ynew].is_blocked() {
-                self.grid[x][y].set(0);
-                let val = self.grid[xnew][ynew].get();
-                self.grid[xnew][ynew].set(val * 2);
-                self.add_score(val * 2);
-                self.grid[xnew][ynew].blocked(true);
-                self.moved = true;
-                tilemoved = true;
-            }
-        else if self.grid[xnew][ynew].is_empty() && !self.grid[x][y].is_empty() {
-            let val = self.grid[x][y].get();
-            self.grid[xnew][ynew].set(val);
-            self.grid[x][y].set(0);
-            self.moved = true;
-            tilemoved = true;
-        }
+#[cfg(feature = "termbox")]
+fn play_in_terminal<F: FnOnce(&UI)>(options: &Options, size: BoardSize, play: F) -> Result<(), String> {
+    termbox::play_in_terminal(options, size, play)
+}
 
-        if tilemoved {
-            self.move_direction(xnew, ynew, d)
-        } else {
-            (x, y)
-        }
-    }
+#[cfg(not(feature = "termbox"))]
+fn play_in_terminal<F: FnOnce(&UI)>(_options: &Options, _size: BoardSize, _play: F) -> Result<(), String> {
+    Err("built without the termbox feature, only --headless play works".to_string())
+}
 
-    fn move_all(&mut self, direc: Direction) {
-        for i in 0.. NCOLS {
-            for j in 0.. NROWS {
-                let tile = self.grid[i][j];
-                if !tile.is_empty() {
-                    let (inew, jnew) = self.move_direction(i, j, direc);
-                    if inew != i || jnew != j {
-                        self.grid[inew][jnew].set_pending(true);
-                        self.tiles_moving.push(Movement {
-                            // it's not grid[i][j], which may have changed
-                            tile: tile,
-                            pold: Point { x: i, y: j},
-                            pnew: Point { x: inew, y: jnew},
-                        });
-                    }
-                }
-            }
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn options(args: &[&str]) -> Options {
+        Options::from_args(args.iter().map(|arg| arg.to_string())).unwrap()
+    }
+
+    #[test]
+    fn verifying_a_replay_stores_no_best_score() {
+        let data_home = std::env::temp_dir().join(format!("2048a-verify-{}", std::process::id()));
+        std::env::set_var("XDG_DATA_HOME", &data_home);
+        let options = options(&["--verify-replay", "game.txt"]);
+        let board = Board::from_text("1024 1024 0 0\n0 0 0 0\n0 0 0 0\n0 0 0 0", options.size, 1).unwrap();
+        let ui = NullUI::new(std::iter::empty());
+
+        // the recording, made by playing the same game
+        let mut played = headless_game(&ui, board.clone(), &options);
+        played.start();
+        played.step(Key::Left);
+        assert_eq!(played.board.score, 2048);
+        let expected = vec![Some((played.board.score, played.board.to_code()))];
+
+        let mut game = headless_game(&ui, board, &options);
+        game.script.push_back(Key::Left);
+        assert_eq!(verify_replay(&mut game, &expected), Ok(1));
+        assert_eq!(game.board.state, State::Won);
+        assert!(!game.keep_high_score);
+        assert!(!data_home.exists());
+    }
+
+    #[test]
+    fn debounced_recording_verifies() {
+        let path = std::env::temp_dir().join(format!("2048a-debounced-{}.txt", std::process::id()));
+        let path = path.to_str().unwrap();
+        let options = options(&["--headless", "--seed", "3"]);
+        let board = Board::from_text("2 2 2 2\n0 0 0 0\n0 0 0 0\n0 0 0 0", options.size, 3).unwrap();
+        let ui = NullUI::new(std::iter::empty());
+
+        let mut played = headless_game(&ui, board.clone(), &options);
+        played.recorder = Some(Recording::create(path, &played.board, options.start_tiles).unwrap());
+        played.debounce_ms = 60_000;
+        played.start();
+        // the second left comes too soon and is dropped, though it would merge the 4s
+        for &key in &[Key::Left, Key::Left, Key::Up] {
+            played.step(key);
         }
+        played.recorder = None;
+        let recording = Recording::load(path).unwrap();
+        let _ = fs::remove_file(path);
+        assert_eq!(recording.keys, vec![Key::Left, Key::Up]);
+
+        let mut game = headless_game(&ui, board, &options);
+        game.script = recording.keys.into_iter().collect();
+        assert_eq!(verify_replay(&mut game, &recording.expected), Ok(2));
+        assert!(game.board.grid == played.board.grid);
+    }
+
+    #[test]
+    fn handicap_goes_only_to_new_boards() {
+        let options = options(&["--headless", "--start-score", "100"]);
+        let fresh = new_board(options.size, 1, &options);
+        assert_eq!((fresh.score, fresh.start_score), (100, 100));
+
+        let path = std::env::temp_dir().join(format!("2048a-handicap-{}.txt", std::process::id()));
+        fs::write(&path, "score: 120\n2 4 0 0\n0 0 0 0\n0 0 0 0\n0 0 0 0\n").unwrap();
+        let mut loaded = load_board(path.to_str().unwrap(), options.size, 1).unwrap();
+        let _ = fs::remove_file(&path);
+        apply_rules(&mut loaded, &options);
+        assert_eq!(loaded.score, 120);
     }
 }
-
-fn main() {
-    let rustbox = match RustBox::init(
-        rustbox::InitOptions {
-            input_mode: rustbox::InputMode::Current,
-            output_mode: rustbox::OutputMode::EightBit,
-            buffer_stderr: true,
-        }) {
-        Result::Ok(v) => v,
-        Result::Err(e) => panic!("{}", e),
-    };
-
-    let ui = TermboxUI::new(&rustbox);
-    let mut game = Game::new(&ui);
-    game.run();
-}
@@ -0,0 +1,26 @@
+//! Profiles, which keep their own high score and key bindings
+
+use std::path::PathBuf;
+
+/// The profile used when none is named, its files sit where they did before
+/// there were profiles
+pub const DEFAULT_PROFILE: &str = "default";
+
+/// Whether `name` can name a profile: up to 32 letters, digits, - and _, so
+/// that it can't lead out of the profiles directory
+pub fn is_valid_profile(name: &str) -> bool {
+    !name.is_empty()
+        && name.len() <= 32
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+/// Where the files of a profile go under a base directory, such as
+/// `$XDG_DATA_HOME`
+pub fn profile_dir(base: PathBuf, profile: &str) -> PathBuf {
+    let dir = base.join("2048a");
+    if profile == DEFAULT_PROFILE {
+        dir
+    } else {
+        dir.join("profiles").join(profile)
+    }
+}
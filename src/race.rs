@@ -0,0 +1,119 @@
+//! Two games side by side, for two players racing to the win target
+
+use board::{Direction, State};
+use game::Game;
+use ui::{Key, UI};
+
+/// Longest wait for a key while nothing moves, so the clocks keep ticking
+const TICK_MS: u64 = 1000;
+
+/// Two games on one screen, the left moved with w, a, s and d and the right
+/// with the arrows; whoever reaches the win target first wins
+///
+/// Restarting, pausing and quitting apply to both. Keys that only make sense
+/// for one player, such as undo, do nothing.
+pub struct Race<'a> {
+    pub ui: &'a UI,
+    /// The left game, then the right one
+    pub games: Vec<Game<'a>>,
+    /// The player who won, 0 for the left
+    pub winner: Option<usize>,
+}
+
+impl<'a> Race<'a> {
+    pub fn new(ui: &'a UI, left: Game<'a>, right: Game<'a>) -> Race<'a> {
+        Race {
+            ui: ui,
+            games: vec![left, right],
+            winner: None,
+        }
+    }
+
+    pub fn run(&mut self) {
+        for game in self.games.iter_mut() {
+            game.start();
+        }
+
+        loop {
+            for game in self.games.iter_mut() {
+                game.tick();
+            }
+            self.draw();
+
+            let animating = self.games.iter().any(|game| {
+                !game.tiles_moving.is_empty() || !game.points_appearing.is_empty() || !game.bells.is_empty()
+            });
+            let frame_ms = self.games[0].frame_ms();
+            // a blitz race ends on time, not at the next tick after it
+            let tick = self.games.iter()
+                .filter(|game| game.board.state == State::Playing)
+                .filter_map(|game| game.time_left())
+                .map(|left| left.as_millis() as u64 + 1)
+                .fold(TICK_MS, u64::min);
+            let key = match self.ui.wait_key(Some(if animating { frame_ms.min(tick) } else { tick })) {
+                Some(key) => key,
+                None => continue,
+            };
+            match key {
+                Key::Char('q') => {
+                    for game in self.games.iter_mut() {
+                        game.quit();
+                    }
+                    break;
+                }
+                Key::Char('r') => {
+                    self.winner = None;
+                    for game in self.games.iter_mut() {
+                        game.reset();
+                    }
+                }
+                Key::Char('p') => {
+                    for game in self.games.iter_mut() {
+                        game.step(key);
+                    }
+                }
+                _ => {
+                    if let Some((player, direc)) = route(key) {
+                        self.play(player, direc);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Moves one player's board, the race is over once either has won
+    fn play(&mut self, player: usize, direc: Direction) {
+        if self.winner.is_some() {
+            return;
+        }
+        self.games[player].step(direc.key());
+        if self.games[player].board.state == State::Won {
+            self.winner = Some(player);
+            self.games[1 - player].hint = Some(format!("Player {} won the race", player + 1));
+        }
+    }
+
+    fn draw(&mut self) {
+        self.ui.clear();
+        for (panel, game) in self.games.iter_mut().enumerate() {
+            self.ui.select_panel(panel);
+            game.draw_frame();
+        }
+        self.ui.present();
+    }
+}
+
+/// The player a key belongs to, and the way it moves their board
+fn route(key: Key) -> Option<(usize, Direction)> {
+    match key {
+        Key::Char('w') => Some((0, Direction::Up)),
+        Key::Char('s') => Some((0, Direction::Down)),
+        Key::Char('a') => Some((0, Direction::Left)),
+        Key::Char('d') => Some((0, Direction::Right)),
+        Key::Up => Some((1, Direction::Up)),
+        Key::Down => Some((1, Direction::Down)),
+        Key::Left => Some((1, Direction::Left)),
+        Key::Right => Some((1, Direction::Right)),
+        _ => None,
+    }
+}
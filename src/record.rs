@@ -0,0 +1,140 @@
+//! Recorded games and scripted moves
+
+use std::fs;
+use std::io::{self, Write};
+
+use board::{Board, BoardSize, Direction};
+use ui::Key;
+
+/// Reads moves for headless play, one of u, d, l, r, or ul, ur, dl, dr for
+/// diagonals, per whitespace separated token
+pub fn parse_moves(text: &str) -> Result<Vec<Key>, String> {
+    text.split_whitespace()
+        .map(|token| match token {
+            "u" => Ok(Key::Up),
+            "d" => Ok(Key::Down),
+            "l" => Ok(Key::Left),
+            "r" => Ok(Key::Right),
+            "ul" => Ok(Direction::UpLeft.key()),
+            "ur" => Ok(Direction::UpRight.key()),
+            "dl" => Ok(Direction::DownLeft.key()),
+            "dr" => Ok(Direction::DownRight.key()),
+            _ => Err(format!("unknown move: {}", token)),
+        })
+        .collect()
+}
+
+/// Name of a key in recordings
+pub fn key_name(key: Key) -> String {
+    match key {
+        Key::Up => "up".to_string(),
+        Key::Down => "down".to_string(),
+        Key::Left => "left".to_string(),
+        Key::Right => "right".to_string(),
+        Key::Char(c) => c.to_string(),
+    }
+}
+
+/// Reads a key written by `key_name`
+pub fn parse_key(name: &str) -> Option<Key> {
+    match name {
+        "up" => Some(Key::Up),
+        "down" => Some(Key::Down),
+        "left" => Some(Key::Left),
+        "right" => Some(Key::Right),
+        _ => {
+            let mut chars = name.chars();
+            match (chars.next(), chars.next()) {
+                (Some(c), None) => Some(Key::Char(c)),
+                _ => None,
+            }
+        }
+    }
+}
+
+/// The rules a game is played by, as a recording keeps them: a replay with
+/// other rules would play out differently from the same keys
+pub fn rules(board: &Board, start_tiles: usize) -> String {
+    format!("four-prob={} target={} scoring={} spawn={} gravity={} wrap={} diagonals={} \
+             start-tiles={} start-score={}",
+            board.four_prob, board.win_target, board.scoring.name(), board.spawn.name(), board.gravity,
+            board.wrap, board.diagonals, start_tiles, board.start_score)
+}
+
+/// A recorded game, enough to play it again exactly
+///
+/// The file starts with `seed: N`, `size: COLSxROWS` and `rules: ...` as
+/// `rules` writes them, followed by one key per line.
+/// A key may be followed by the score and the tiles, as `Board::to_code` writes
+/// them, that it led to.
+pub struct Recording {
+    pub seed: u64,
+    pub size: BoardSize,
+    /// None in recordings made before the rules were kept
+    pub rules: Option<String>,
+    pub keys: Vec<Key>,
+    /// Score and tiles after each key, where they were recorded
+    pub expected: Vec<Option<(usize, String)>>,
+}
+
+impl Recording {
+    /// Creates a recording file for a game on `board` and writes its header
+    pub fn create(path: &str, board: &Board, start_tiles: usize) -> io::Result<fs::File> {
+        let mut file = fs::File::create(path)?;
+        writeln!(file, "seed: {}", board.seed)?;
+        writeln!(file, "size: {}x{}", board.size.cols, board.size.rows)?;
+        writeln!(file, "rules: {}", rules(board, start_tiles))?;
+        Ok(file)
+    }
+
+    pub fn load(path: &str) -> Result<Recording, String> {
+        let text = fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+        Recording::parse(&text).map_err(|e| format!("{}: {}", path, e))
+    }
+
+    pub fn parse(text: &str) -> Result<Recording, String> {
+        let mut recording = Recording {
+            seed: 0,
+            size: BoardSize::default(),
+            rules: None,
+            keys: Vec::new(),
+            expected: Vec::new(),
+        };
+        let mut has_seed = false;
+        for (number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            let invalid = || format!("line {}: invalid entry: {}", number + 1, line);
+            if line.is_empty() {
+                continue;
+            } else if line.starts_with("seed:") {
+                recording.seed = line["seed:".len()..].trim().parse().map_err(|_| invalid())?;
+                has_seed = true;
+            } else if line.starts_with("rules:") {
+                recording.rules = Some(line["rules:".len()..].trim().to_string());
+            } else if line.starts_with("size:") {
+                let size = line["size:".len()..].trim();
+                let mut parts = size.split('x').map(|n| n.parse());
+                recording.size = match (parts.next(), parts.next(), parts.next()) {
+                    (Some(Ok(cols)), Some(Ok(rows)), None) => BoardSize { cols: cols, rows: rows },
+                    _ => return Err(invalid()),
+                };
+            } else {
+                let mut fields = line.split_whitespace();
+                let key = fields.next().and_then(parse_key).ok_or_else(invalid)?;
+                let expected = match (fields.next(), fields.next(), fields.next()) {
+                    (None, _, _) => None,
+                    (Some(score), Some(tiles), None) => {
+                        Some((score.parse().map_err(|_| invalid())?, tiles.to_string()))
+                    }
+                    _ => return Err(invalid()),
+                };
+                recording.keys.push(key);
+                recording.expected.push(expected);
+            }
+        }
+        if !has_seed {
+            return Err("no seed recorded".to_string());
+        }
+        Ok(recording)
+    }
+}
@@ -0,0 +1,75 @@
+//! Watching a game again, its keys played back on a copy of how it started
+
+use std::collections::VecDeque;
+
+use game::Game;
+use ui::{Key, UI};
+
+/// A game played back a key at a time, at a steady pace or stepped through
+///
+/// p or space pauses and resumes, → plays the next key and pauses, and q stops
+/// watching.
+pub struct Replay<'a> {
+    pub ui: &'a UI,
+    /// The copy being played, which starts out as the original did
+    pub game: Game<'a>,
+    /// Keys still to play
+    pub keys: VecDeque<Key>,
+    /// Time between keys in milliseconds
+    pub delay_ms: u64,
+    /// Waiting to be resumed or stepped
+    pub paused: bool,
+}
+
+impl<'a> Replay<'a> {
+    pub fn new(mut game: Game<'a>, keys: Vec<Key>, delay_ms: u64) -> Replay<'a> {
+        game.tag = Some("REPLAY".to_string());
+        game.instructions = Some("p pauses, → steps, q stops".to_string());
+        Replay {
+            ui: game.ui,
+            game: game,
+            keys: keys.into_iter().collect(),
+            delay_ms: delay_ms,
+            paused: false,
+        }
+    }
+
+    pub fn run(&mut self) {
+        self.game.start();
+
+        loop {
+            self.ui.clear();
+            self.game.draw_frame();
+            self.ui.present();
+
+            let animating = !self.game.tiles_moving.is_empty() || !self.game.points_appearing.is_empty();
+            let timeout = if animating {
+                Some(self.game.frame_ms())
+            } else if self.paused || self.keys.is_empty() {
+                None
+            } else {
+                Some(self.delay_ms)
+            };
+            match self.ui.wait_key(timeout) {
+                Some(Key::Char('q')) => break,
+                Some(Key::Char('p')) => self.paused = !self.paused,
+                Some(Key::Right) => {
+                    self.paused = true;
+                    self.next();
+                }
+                Some(_) => {}
+                // the time between keys is counted once the animation is over
+                None if animating || self.paused => {}
+                None => self.next(),
+            }
+        }
+    }
+
+    /// Plays the next key, if there is one left
+    fn next(&mut self) {
+        self.game.finish_animation();
+        if let Some(key) = self.keys.pop_front() {
+            self.game.step(key);
+        }
+    }
+}
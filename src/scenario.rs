@@ -0,0 +1,141 @@
+//! Scenarios: a board set up, played and checked from a few lines of text,
+//! for turning bug reports into something that can be run again
+//!
+//! One command per line, anything after # is a comment:
+//!
+//! ```text
+//! seed 7           # new tiles as with --seed 7
+//! set 0 0 2        # a 2 in column 0, row 0
+//! set 1 0 2
+//! wall 3 0
+//! move left        # or l, and any key with `key`, such as key u to undo
+//! assert tile 0 0 4
+//! assert score 4
+//! assert state playing
+//! ```
+
+use board::{Direction, State};
+use game::Game;
+use record::{parse_key, parse_moves};
+
+/// Runs a scenario on `game`, whose board is where it starts from; moves go
+/// through `Game::step` like a player's keys. Returns how many assertions
+/// held, or the first line that failed and why.
+pub fn run_scenario(game: &mut Game, text: &str) -> Result<usize, String> {
+    game.animation_ms = 0;
+    let mut checked = 0;
+    for (number, line) in text.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or("");
+        let words: Vec<&str> = line.split_whitespace().collect();
+        if words.is_empty() {
+            continue;
+        }
+        run_command(game, &words, &mut checked).map_err(|e| format!("line {}: {}", number + 1, e))?;
+    }
+    Ok(checked)
+}
+
+fn run_command(game: &mut Game, words: &[&str], checked: &mut usize) -> Result<(), String> {
+    match *words {
+        ["seed", seed] => {
+            game.board.reseed(seed.parse().map_err(|_| format!("not a seed: {}", seed))?);
+            Ok(())
+        }
+        ["set", x, y, value] => game.board.spawn_at((number(x)?, number(y)?), number(value)?),
+        ["wall", x, y] => game.board.add_wall((number(x)?, number(y)?)),
+        ["move", name] => {
+            let key = match Direction::from_name(name) {
+                Some(direc) => direc.key(),
+                None => match parse_moves(name)?.as_slice() {
+                    [key] => *key,
+                    _ => return Err(format!("unknown move: {}", name)),
+                },
+            };
+            game.step(key);
+            game.finish_animation();
+            Ok(())
+        }
+        ["key", name] => {
+            let key = parse_key(name).ok_or_else(|| format!("unknown key: {}", name))?;
+            game.step(key);
+            game.finish_animation();
+            Ok(())
+        }
+        ["assert", ref check @ ..] => {
+            check_assertion(game, check)?;
+            *checked += 1;
+            Ok(())
+        }
+        _ => Err(format!("unknown command: {}", words.join(" "))),
+    }
+}
+
+fn check_assertion(game: &Game, words: &[&str]) -> Result<(), String> {
+    match *words {
+        ["tile", x, y, value] => {
+            let (x, y, value) = (number(x)?, number(y)?, number(value)?);
+            if x >= game.board.size.cols || y >= game.board.size.rows {
+                return Err(format!("({}, {}) is off the board", x, y));
+            }
+            let found = game.board.grid[x][y].value();
+            expect(&format!("tile ({}, {})", x, y), value, found)
+        }
+        ["score", score] => expect("score", number(score)?, game.board.score),
+        ["moves", moves] => expect("moves", number(moves)?, game.moves),
+        ["state", name] => {
+            let state = match name {
+                "playing" => State::Playing,
+                "won" => State::Won,
+                "lost" => State::Lost,
+                _ => return Err(format!("unknown state: {}", name)),
+            };
+            if game.board.state != state {
+                let found = format!("{:?}", game.board.state).to_lowercase();
+                return Err(format!("expected the game to be {}, it is {}", name, found));
+            }
+            Ok(())
+        }
+        _ => Err(format!("unknown assertion: {}", words.join(" "))),
+    }
+}
+
+fn expect(what: &str, expected: usize, found: usize) -> Result<(), String> {
+    if expected != found {
+        return Err(format!("expected {} to be {}, found {}", what, expected, found));
+    }
+    Ok(())
+}
+
+fn number(word: &str) -> Result<usize, String> {
+    word.parse().map_err(|_| format!("not a number: {}", word))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use board::{Board, BoardSize};
+    use ui::NullUI;
+
+    const SCRIPT: &str = "seed 7\nset 0 0 2\nset 1 0 2\nmove left\nassert tile 0 0 4\nmove down\n";
+
+    fn grid_after(seed: u64, script: &str) -> Vec<Vec<usize>> {
+        let ui = NullUI::new(::std::iter::empty());
+        let mut game = Game::new(&ui, Board::new(BoardSize::default(), seed));
+        run_scenario(&mut game, script).unwrap();
+        game.board.grid.iter().map(|column| column.iter().map(|tile| tile.value()).collect()).collect()
+    }
+
+    #[test]
+    fn seed_command_fixes_new_tiles() {
+        assert_eq!(grid_after(1, SCRIPT), grid_after(99, SCRIPT));
+    }
+
+    #[test]
+    fn failing_line_is_named() {
+        let ui = NullUI::new(::std::iter::empty());
+        let mut game = Game::new(&ui, Board::new(BoardSize::default(), 0));
+        let error = run_scenario(&mut game, "set 0 0 2\n\nassert score 4\n").unwrap_err();
+        assert_eq!(error, "line 3: expected score to be 4, found 0");
+        assert!(run_scenario(&mut game, "seed x").unwrap_err().contains("not a seed"));
+    }
+}
@@ -0,0 +1,324 @@
+//! Strategies for automatic play
+
+use std::collections::HashMap;
+
+use rand::Rng;
+
+use board::{Board, Direction, Tile};
+
+/// How many moves ahead expectimax looks when no depth is given
+pub const DEFAULT_DEPTH: usize = 2;
+
+/// Chooses moves for automatic play
+pub trait Strategy {
+    /// The next move, None when nothing can move
+    fn choose(&self, board: &Board) -> Option<Direction>;
+}
+
+/// Takes the move that looks best right after making it
+pub struct Greedy;
+
+/// Keeps the tiles packed towards the bottom left corner
+pub struct CornerBias;
+
+/// Takes any move that changes the board
+pub struct Random;
+
+/// Looks `depth` moves ahead, averaging over where new tiles may appear
+pub struct Expectimax {
+    pub depth: usize,
+}
+
+impl Strategy for Greedy {
+    fn choose(&self, board: &Board) -> Option<Direction> {
+        best_move(board)
+    }
+}
+
+impl Strategy for CornerBias {
+    fn choose(&self, board: &Board) -> Option<Direction> {
+        [Direction::Down, Direction::Left, Direction::Right, Direction::Up]
+            .iter()
+            .cloned()
+            .find(|&direc| board.clone_and_move(direc).1)
+    }
+}
+
+impl Strategy for Random {
+    fn choose(&self, board: &Board) -> Option<Direction> {
+        let legal = board.available_moves();
+        // a copy of the board's RNG keeps seeded games reproducible
+        let mut rng = board.rng.clone();
+        rng.choose(&legal).cloned()
+    }
+}
+
+impl Strategy for Expectimax {
+    fn choose(&self, board: &Board) -> Option<Direction> {
+        best_move_expectimax(board, self.depth)
+    }
+}
+
+/// Looks up a strategy by the name given to --strategy, `depth` only matters
+/// to the ones that search
+pub fn strategy_by_name(name: &str, depth: usize) -> Option<Box<Strategy>> {
+    match name {
+        "greedy" => Some(Box::new(Greedy)),
+        "corner" => Some(Box::new(CornerBias)),
+        "random" => Some(Box::new(Random)),
+        "expectimax" => Some(Box::new(Expectimax { depth: depth })),
+        _ => None,
+    }
+}
+
+/// The board after moving in `direc`, None if nothing would move
+pub fn try_move(board: &Board, direc: Direction) -> Option<Board> {
+    match board.clone_and_move(direc) {
+        (trial, true) => Some(trial),
+        (_, false) => None,
+    }
+}
+
+/// Picks the direction whose result scores best by `evaluate`, None when stuck
+pub fn best_move(board: &Board) -> Option<Direction> {
+    let mut best = None;
+    let mut best_value = 0.0;
+    for direc in board.directions() {
+        let trial = match try_move(board, direc) {
+            Some(trial) => trial,
+            None => continue,
+        };
+        let value = evaluate(&trial);
+        if best.is_none() || value > best_value {
+            best = Some(direc);
+            best_value = value;
+        }
+    }
+    best
+}
+
+/// Values of positions already searched, by `Board::pack` and the depth left
+type Cache = HashMap<(u64, usize), f64>;
+
+/// Picks the direction with the best expected value `depth` moves ahead,
+/// None when stuck
+pub fn best_move_expectimax(board: &Board, depth: usize) -> Option<Direction> {
+    // each search starts afresh, values depend on the board's chance of a 4
+    let mut cache = Cache::new();
+    let mut best = None;
+    let mut best_value = 0.0;
+    for direc in board.directions() {
+        let trial = match try_move(board, direc) {
+            Some(trial) => trial,
+            None => continue,
+        };
+        let value = expect_spawn(&trial, depth.max(1), &mut cache);
+        if best.is_none() || value > best_value {
+            best = Some(direc);
+            best_value = value;
+        }
+    }
+    best
+}
+
+/// Value of a position with the player to move: the best of the moves, each
+/// followed by a new tile, down to `depth` moves where `evaluate` takes over
+///
+/// A stuck position is worth 0, no better than the emptiest board.
+pub fn expectimax(board: &Board, depth: usize) -> f64 {
+    search(board, depth, &mut Cache::new())
+}
+
+/// `expectimax`, remembering the positions it comes across again by other
+/// moves; boards too big for `Board::pack` are searched every time
+fn search(board: &Board, depth: usize, cache: &mut Cache) -> f64 {
+    if depth == 0 {
+        return evaluate(board);
+    }
+    let key = board.pack().map(|packed| (packed, depth));
+    if let Some(value) = key.and_then(|key| cache.get(&key).cloned()) {
+        return value;
+    }
+    let value = board.directions()
+        .into_iter()
+        .filter_map(|direc| try_move(board, direc))
+        .map(|trial| expect_spawn(&trial, depth, cache))
+        .fold(0.0, f64::max);
+    if let Some(key) = key {
+        cache.insert(key, value);
+    }
+    value
+}
+
+/// Average value of a position after a move, over every empty cell a new
+/// tile may take and the chances of it being a 2 or a 4
+fn expect_spawn(board: &Board, depth: usize, cache: &mut Cache) -> f64 {
+    let mut empty = Vec::new();
+    for x in 0..board.size.cols {
+        for y in 0..board.size.rows {
+            if board.grid[x][y].is_empty() && !board.is_wall(x, y) {
+                empty.push((x, y));
+            }
+        }
+    }
+    if empty.is_empty() {
+        return search(board, depth - 1, cache);
+    }
+
+    let mut total = 0.0;
+    for &(x, y) in &empty {
+        for &(value, chance) in &[(2, 1.0 - board.four_prob), (4, board.four_prob)] {
+            if chance == 0.0 {
+                continue;
+            }
+            let mut spawned = board.clone();
+            spawned.grid[x][y] = Tile::from_value(value);
+            total += chance * search(&spawned, depth - 1, cache);
+        }
+    }
+    total / empty.len() as f64
+}
+
+/// Heuristic value of a position: empty cells, neighbours that could merge,
+/// and the largest tile sitting in a corner
+pub fn evaluate(board: &Board) -> f64 {
+    let cols = board.size.cols;
+    let rows = board.size.rows;
+    let mut empty = 0;
+    let mut merges = 0;
+    for x in 0..cols {
+        for y in 0..rows {
+            let value = board.grid[x][y].value();
+            if value == 0 {
+                if !board.is_wall(x, y) {
+                    empty += 1;
+                }
+                continue;
+            }
+            if x + 1 < cols && board.grid[x + 1][y].value() == value {
+                merges += 1;
+            }
+            if y + 1 < rows && board.grid[x][y + 1].value() == value {
+                merges += 1;
+            }
+        }
+    }
+
+    let max = board.max_tile();
+    let corners = [(0, 0), (cols - 1, 0), (0, rows - 1), (cols - 1, rows - 1)];
+    let cornered = max > 0 && corners.iter().any(|&(x, y)| board.grid[x][y].value() == max);
+    let corner_bonus = if cornered { (max as f64).log2() } else { 0.0 };
+
+    empty as f64 * 10.0 + merges as f64 * 5.0 + corner_bonus * 10.0
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Instant;
+
+    use super::*;
+    use batch::run_batch;
+    use board::BoardSize;
+    use game::Game;
+
+    /// `search` without a cache, to check it and time it against
+    fn uncached(board: &Board, depth: usize) -> f64 {
+        if depth == 0 {
+            return evaluate(board);
+        }
+        board.directions()
+            .into_iter()
+            .filter_map(|direc| try_move(board, direc))
+            .map(|trial| {
+                let empty: Vec<(usize, usize)> = (0..trial.size.cols)
+                    .flat_map(|x| (0..trial.size.rows).map(move |y| (x, y)))
+                    .filter(|&(x, y)| trial.grid[x][y].is_empty() && !trial.is_wall(x, y))
+                    .collect();
+                if empty.is_empty() {
+                    return uncached(&trial, depth - 1);
+                }
+                let mut total = 0.0;
+                for &(x, y) in &empty {
+                    for &(value, chance) in &[(2, 1.0 - trial.four_prob), (4, trial.four_prob)] {
+                        let mut spawned = trial.clone();
+                        spawned.grid[x][y] = Tile::from_value(value);
+                        total += chance * uncached(&spawned, depth - 1);
+                    }
+                }
+                total / empty.len() as f64
+            })
+            .fold(0.0, f64::max)
+    }
+
+    fn spawn(board: &mut Board) {
+        if let Some(new) = board.add_tile() {
+            board.spawn_at((new.position.x, new.position.y), new.value).unwrap();
+        }
+    }
+
+    /// Positions from a greedy game, some way in
+    fn positions() -> Vec<Board> {
+        let mut board = Board::new(BoardSize::default(), 3);
+        spawn(&mut board);
+        spawn(&mut board);
+        let mut positions = Vec::new();
+        for _ in 0..60 {
+            let direc = match best_move(&board) {
+                Some(direc) => direc,
+                None => break,
+            };
+            board.move_all(direc);
+            spawn(&mut board);
+            positions.push(board.clone());
+        }
+        positions.into_iter().skip(20).step_by(10).collect()
+    }
+
+    #[test]
+    fn cache_keeps_values() {
+        for board in positions() {
+            assert_eq!(expectimax(&board, 2), uncached(&board, 2));
+        }
+    }
+
+    /// Times depth 3 with and without the cache, run with
+    /// `cargo test --release -- --ignored --nocapture`
+    #[test]
+    #[ignore]
+    fn bench_cache() {
+        let positions = positions();
+        let started = Instant::now();
+        let cached: Vec<f64> = positions.iter().map(|board| expectimax(board, 3)).collect();
+        let with_cache = started.elapsed();
+        let started = Instant::now();
+        let plain: Vec<f64> = positions.iter().map(|board| uncached(board, 3)).collect();
+        let without = started.elapsed();
+        println!("{} positions at depth 3: {:?} with the cache, {:?} without, {:.1} times as quick",
+                 positions.len(), with_cache, without,
+                 without.as_secs_f64() / with_cache.as_secs_f64());
+        assert_eq!(cached, plain);
+    }
+
+    #[test]
+    fn expectimax_moves_when_it_can() {
+        let mut board = Board::new(BoardSize::default(), 1);
+        board.spawn_at((0, 0), 2).unwrap();
+        board.spawn_at((1, 0), 2).unwrap();
+        assert!(best_move_expectimax(&board, 2).is_some());
+        assert!(expectimax(&board, 1) > 0.0);
+    }
+
+    /// Plays a few headless games, run with `cargo test --release -- --ignored --nocapture`
+    #[test]
+    #[ignore]
+    fn bench_expectimax_games() {
+        let started = Instant::now();
+        let stats = run_batch(&Expectimax { depth: DEFAULT_DEPTH }, 5, 1, |ui, seed| {
+            Game::new(ui, Board::new(BoardSize::default(), seed))
+        });
+        let tiles: usize = stats.max_tiles.iter().map(|(&tile, &games)| tile * games).sum();
+        println!("{}Mean best tile: {:.1}  Time: {:?}",
+                 stats, tiles as f64 / stats.games as f64, started.elapsed());
+        assert_eq!(stats.games, 5);
+    }
+}
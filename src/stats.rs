@@ -0,0 +1,50 @@
+//! A log of finished games, for looking back over many of them
+
+use std::fmt;
+use std::fs;
+use std::io::{self, Write};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use board::Board;
+
+/// First line of a new log
+const HEADER: &str = "timestamp,seed,score,moves,max_tile,outcome";
+
+/// How a logged game ended
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Outcome {
+    Won,
+    Lost,
+    /// Left while still being played
+    Quit,
+}
+
+impl fmt::Display for Outcome {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(self, f)
+    }
+}
+
+/// Appends a CSV line for every game, see `StatsLog::log`
+pub struct StatsLog {
+    file: fs::File,
+}
+
+impl StatsLog {
+    /// Opens the log for appending, a new or empty file gets the header first
+    pub fn open(path: &str) -> io::Result<StatsLog> {
+        let mut file = fs::OpenOptions::new().create(true).append(true).open(path)?;
+        if file.metadata()?.len() == 0 {
+            writeln!(file, "{}", HEADER)?;
+        }
+        Ok(StatsLog { file: file })
+    }
+
+    /// Writes the line of a game that has just ended, with the time in seconds since the epoch
+    pub fn log(&mut self, board: &Board, moves: usize, outcome: Outcome) -> io::Result<()> {
+        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+        writeln!(self.file, "{},{},{},{},{},{}",
+                 timestamp, board.seed, board.score, moves, board.max_tile(), outcome)?;
+        self.file.flush()
+    }
+}
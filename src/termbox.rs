@@ -0,0 +1,1217 @@
+//! Drawing in a terminal with termbox, and the key bindings read for it
+
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+use game2048::{abbreviate, BoardSize, Key, State, Summary, Tile, UI};
+use game2048::profile::profile_dir;
+use rustbox::{self, Color, RustBox};
+use rustbox::Key as RKey;
+use toml;
+
+use Options;
+
+/// Lines above the board: stats, then score and best score side by side
+const HEADER_LINES: usize = 2;
+/// Lines below the board: instructions, status, then hints
+const FOOTER_LINES: usize = 3;
+/// Columns, or twice as many rows, a drag has to cover to make a move
+const DRAG_COLUMNS: i32 = 4;
+/// Mouse events further apart than this start a new drag
+const DRAG_GAP_MS: u64 = 300;
+/// Columns between the boards of a race
+const PANEL_GAP: usize = 4;
+/// How often waiting for a key looks for an interrupt
+const INTERRUPT_CHECK_MS: u64 = 250;
+
+/// Set by SIGINT or SIGTERM; the game then quits as if q had been pressed,
+/// so it saves its scores and rustbox gets to restore the terminal
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+/// Size of the cells and of the gutters around them, in characters
+#[derive(Clone, Copy)]
+struct CellLayout {
+    width: usize,
+    height: usize,
+    /// Columns between cells and at the sides of the board
+    gap_x: usize,
+    /// Rows between cells and at the top and bottom of the board
+    gap_y: usize,
+}
+
+/// Cells as blocks with the number in the middle
+const NORMAL_CELLS: CellLayout = CellLayout { width: 6, height: 3, gap_x: 2, gap_y: 1 };
+/// A line for each cell, for narrow terminals
+const COMPACT_CELLS: CellLayout = CellLayout { width: 4, height: 1, gap_x: 1, gap_y: 1 };
+
+impl CellLayout {
+    /// Width of the drawn board in characters
+    fn board_width(&self, size: BoardSize) -> usize {
+        self.gap_x + (self.width + self.gap_x) * size.cols
+    }
+
+    /// Height of the drawn board in characters
+    fn board_height(&self, size: BoardSize) -> usize {
+        self.gap_y + (self.height + self.gap_y) * size.rows
+    }
+
+    /// Top left corner of a cell, relative to the board's
+    fn cell_origin(&self, col: usize, row: usize) -> (usize, usize) {
+        (self.gap_x + col * (self.width + self.gap_x), self.gap_y + row * (self.height + self.gap_y))
+    }
+
+    /// Colour of each character of the empty board, by column then row: the
+    /// theme's empty colour in the cells and its background around them
+    fn board_colours(&self, size: BoardSize, theme: &Theme) -> Vec<Vec<Color>> {
+        let board_width = self.board_width(size);
+        let board_height = self.board_height(size);
+
+        let mut board = vec![vec![theme.background; board_height]; board_width];
+
+        for i in 0..size.cols {
+            for j in 0..size.rows {
+                let (left, top) = self.cell_origin(i, j);
+                if left + self.width < board_width && top + self.height < board_height {
+                    for x in left .. left + self.width {
+                        for y in top .. top + self.height {
+                            board[x][y] = theme.empty;
+                        }
+                    }
+                }
+            }
+        }
+        board
+    }
+}
+
+/// Colours used to draw the board
+#[derive(Clone)]
+pub struct Theme {
+    /// Around and between the cells
+    background: Color,
+    /// Cells without a tile
+    empty: Color,
+    /// Text and fill of the tiles from 2 upwards, each twice the one before
+    tiles: Vec<(Color, Color)>,
+    /// Text and fill of tiles beyond the end of `tiles`
+    other: (Color, Color),
+}
+
+impl Theme {
+    fn classic() -> Theme {
+        let text = Color::Byte(232);
+        let light = Color::Byte(231);
+        // past 2048 the tiles darken, with light text
+        let beyond = [93, 57, 27, 23]
+            .iter()
+            .map(|&c| (light, Color::Byte(c)));
+        Theme {
+            background: Color::Byte(137),
+            empty: Color::Byte(180),
+            tiles: [224, 222, 216, 209, 202, 203, 230, 226, 193, 190, 214]
+                .iter()
+                .map(|&c| (text, Color::Byte(c)))
+                .chain(beyond)
+                .collect(),
+            other: (light, Color::Byte(235)),
+        }
+    }
+
+    /// Only black and white, for terminals without 256 colours
+    fn mono() -> Theme {
+        Theme {
+            background: Color::White,
+            empty: Color::Black,
+            tiles: Vec::new(),
+            other: (Color::White, Color::Black),
+        }
+    }
+
+    /// The eight basic colours, for terminals in 16 colour mode
+    fn sixteen() -> Theme {
+        Theme {
+            background: Color::Blue,
+            empty: Color::Black,
+            tiles: vec![
+                (Color::Black, Color::White),
+                (Color::Black, Color::Yellow),
+                (Color::White, Color::Red),
+                (Color::Black, Color::Cyan),
+                (Color::White, Color::Magenta),
+                (Color::Black, Color::Green),
+                (Color::Black, Color::White),
+                (Color::Black, Color::Yellow),
+                (Color::White, Color::Red),
+                (Color::Black, Color::Cyan),
+                (Color::White, Color::Magenta),
+            ],
+            other: (Color::Black, Color::Green),
+        }
+    }
+
+    /// Dark text on bright tiles, with the largest tiles inverted
+    fn high_contrast() -> Theme {
+        let dark = Color::Byte(16);
+        let light = Color::Byte(231);
+        Theme {
+            background: Color::Byte(16),
+            empty: Color::Byte(240),
+            tiles: vec![
+                (dark, Color::Byte(231)),
+                (dark, Color::Byte(229)),
+                (dark, Color::Byte(221)),
+                (dark, Color::Byte(214)),
+                (dark, Color::Byte(208)),
+                (dark, Color::Byte(196)),
+                (dark, Color::Byte(51)),
+                (dark, Color::Byte(46)),
+                (dark, Color::Byte(201)),
+                (light, Color::Byte(21)),
+                (light, Color::Byte(90)),
+            ],
+            other: (light, Color::Byte(52)),
+        }
+    }
+
+    /// Looks up a theme by the name given to --theme
+    pub fn by_name(name: &str) -> Option<Theme> {
+        match name {
+            "classic" => Some(Theme::classic()),
+            "mono" => Some(Theme::mono()),
+            "high-contrast" => Some(Theme::high_contrast()),
+            _ => None,
+        }
+    }
+
+    /// Whether the theme shows properly without 256 colours
+    fn is_basic(&self) -> bool {
+        let is_basic = |c: &Color| match *c {
+            Color::Byte(_) => false,
+            _ => true,
+        };
+        is_basic(&self.background)
+            && is_basic(&self.empty)
+            && self.tiles.iter().chain(Some(&self.other)).all(|&(fg, bg)| is_basic(&fg) && is_basic(&bg))
+    }
+
+    /// Text and fill colours of a tile
+    fn tile_colours(&self, value: usize) -> (Color, Color) {
+        if value < 2 {
+            return self.other;
+        }
+        // 2 is the first entry, 4 the second and so on
+        let index = value.trailing_zeros() as usize - 1;
+        self.tiles.get(index).cloned().unwrap_or(self.other)
+    }
+}
+
+struct TermboxUI<'a> {
+    rustbox: &'a RustBox,
+    keys: KeyBindings,
+    theme: Theme,
+    size: BoardSize,
+    cells: CellLayout,
+    board: Vec<Vec<Color>>,
+    /// Top left corner of everything drawn, chosen to centre it in the terminal
+    x_origin: Cell<usize>,
+    y_origin: Cell<usize>,
+    /// Boards shown side by side, 2 in a race
+    panels: usize,
+    /// Left edge of the first panel, `x_origin` moves along from it with the panel
+    panels_left: Cell<usize>,
+    /// The panel being drawn, see `select_panel`
+    panel: Cell<usize>,
+    /// Draw in the terminal's default colours, tiles as bracketed numbers
+    plain: bool,
+    /// Mark each tile with its power of two, for telling them apart without colour
+    labels: bool,
+    /// Show big tiles in thousands or millions, see `abbreviate`
+    abbrev: bool,
+    /// Show the score in the terminal's title, see `draw_title`
+    title: bool,
+    /// The best score on a line of its own under the score, and the stats
+    /// cut to the board's width, for boards too narrow for both
+    stacked: bool,
+    /// Widest score line drawn since the screen was cleared, `fits` only
+    /// passes a board it fits in
+    score_width: Cell<usize>,
+    /// The title last set, so it is only sent when it changes
+    shown_title: RefCell<String>,
+    /// Why reading input failed, the game is asked to quit when it does
+    error: RefCell<Option<String>>,
+    /// Where the left button went down for the drag in progress, and when
+    /// it was last seen
+    drag: Cell<Option<(i32, i32, Instant)>>,
+}
+
+impl<'a> UI for TermboxUI<'a> {
+    fn wait_key(&self, timeout: Option<u64>) -> Option<Key> {
+        let event = match timeout {
+            Some(time) => self.rustbox.peek_event(std::time::Duration::from_millis(time), false),
+            // in turns, a signal may not wake up a wait for input
+            None => loop {
+                match self.rustbox.peek_event(Duration::from_millis(INTERRUPT_CHECK_MS), false) {
+                    Ok(rustbox::Event::NoEvent) if !INTERRUPTED.load(Ordering::SeqCst) => continue,
+                    event => break event,
+                }
+            },
+        };
+        if INTERRUPTED.load(Ordering::SeqCst) {
+            return Some(Key::Char('q'));
+        }
+        match event {
+            Ok(rustbox::Event::KeyEvent(key)) => self.keys.map(key),
+            Ok(rustbox::Event::MouseEvent(button, x, y)) => self.swipe(button, x, y),
+            Ok(rustbox::Event::ResizeEvent(_, _)) => {
+                // the caller redraws everything once no key comes back
+                self.place();
+                self.rustbox.clear();
+                None
+            }
+            Err(e) => {
+                // quitting lets the game save its scores before the terminal is restored
+                *self.error.borrow_mut() = Some(format!("reading input failed: {}", e));
+                Some(Key::Char('q'))
+            }
+            _ => None,
+        }
+    }
+
+    fn draw_bg(&self, x_offset: usize, y_offset: usize) {
+        if self.plain {
+            return self.draw_plain_bg(x_offset, y_offset);
+        }
+        for x in 0 .. self.board_width() {
+            for y in 0 .. self.board_height() {
+                let color = self.board[x][y];
+                self.rustbox.print_char(self.x_origin.get() + x + x_offset,
+                                   self.board_top() + y + y_offset,
+                                   rustbox::RB_NORMAL,
+                                   color,
+                                   color,
+                                   ' ');
+            }
+        }
+    }
+
+    fn draw_grid(&self, grid: &[Vec<Tile>]) {
+        for x in 0.. self.size.cols {
+            for y in 0.. self.size.rows {
+                self.draw_tile(x, y, grid[x][y], None)
+            }
+        }
+    }
+
+    fn draw_preview(&self, grid: &[Vec<Tile>]) {
+        // only the numbers, in the colour their tiles would be, over empty cells
+        let cells = self.cells;
+        for (col, column) in grid.iter().enumerate() {
+            for (row, tile) in column.iter().enumerate() {
+                if tile.value() == 0 {
+                    continue;
+                }
+                let mut num = tile.value().to_string();
+                if self.abbrev || num.len() > cells.width {
+                    num = abbreviate(tile.value());
+                }
+                num.truncate(cells.width);
+                let (x, y) = cells.cell_origin(col, row);
+                let x = self.x_origin.get() + x + centred_offset(cells.width, num.len());
+                let y = self.board_top() + y + cells.height / 2;
+                let (fg, bg) = if self.plain {
+                    (Color::Default, Color::Default)
+                } else {
+                    (self.theme.tile_colours(tile.value()).1, self.theme.empty)
+                };
+                self.draw_text(x, y, num, fg, bg);
+            }
+        }
+    }
+
+    fn draw_tile(&self, col: usize, row: usize, tile: Tile, partial: Option<f32>) {
+        self.draw_tile_between(tile, col as f32, row as f32, partial);
+    }
+
+    fn draw_tile_between(&self, tile: Tile, col: f32, row: f32, partial: Option<f32>) {
+        // past the gutter on the left and the top
+        let cells = self.cells;
+        let x_offset = (self.x_origin.get() + cells.gap_x) as f32;
+        let y_offset = (self.board_top() + cells.gap_y) as f32;
+
+        let x_coord = x_offset + col * (cells.width + cells.gap_x) as f32;
+        let y_coord = y_offset + row * (cells.height + cells.gap_y) as f32;
+
+        self.draw_tile_at(tile, x_coord as usize, y_coord as usize, partial);
+    }
+
+    fn draw_tile_at(&self, tile: Tile, x_coord: usize, y_coord: usize, partial: Option<f32>) {
+        let cells = self.cells;
+        let x_centre = x_coord + cells.width / 2;
+        let y_centre = y_coord + cells.height / 2;
+
+        let mut num = tile.get().to_string();
+        if self.abbrev || num.len() > cells.width {
+            num = abbreviate(tile.get());
+        }
+        // never write past the edge of the cell
+        num.truncate(cells.width);
+        let x_text_pos = x_coord + centred_offset(cells.width, num.len());
+        let (text_colour, tile_colour) = self.theme.tile_colours(tile.get());
+        if num != "0" && self.plain {
+            // the brackets go when the number needs their room
+            let inner = cells.width - 2;
+            let text = if num.len() <= inner {
+                let left = centred_offset(inner, num.len());
+                format!("[{}{}{}]", " ".repeat(left), num, " ".repeat(inner - num.len() - left))
+            } else {
+                num
+            };
+            let x = x_coord + centred_offset(cells.width, text.len());
+            self.draw_text(x, y_centre, text, Color::Default, Color::Default);
+        } else if num != "0" {
+            if let Some(ratio) = partial {
+                // a ratio above 1 spills into the gaps around the cell
+                for x in x_coord.saturating_sub(cells.gap_x) .. x_coord + cells.width + cells.gap_x {
+                    for y in y_coord.saturating_sub(cells.gap_y) .. y_coord + cells.height + cells.gap_y {
+                        if (x as f32 - x_centre as f32).abs() < cells.width as f32 * ratio / 2.0
+                            && (y as f32 - y_centre as f32).abs() < cells.height as f32 * ratio / 2.0 {
+                            self.rustbox.print_char(x, y,
+                                                    rustbox::RB_NORMAL,
+                                                    tile_colour,
+                                                    tile_colour, ' ');
+                        }
+                    }
+                }
+            } else {
+                self.draw_rectangle(x_coord,
+                                    y_coord,
+                                    cells.width,
+                                    cells.height,
+                                    tile_colour,
+                );
+            }
+            self.rustbox.print(x_text_pos,
+                               y_centre,
+                               rustbox::RB_NORMAL,
+                               text_colour,
+                               tile_colour,
+                               &num);
+        }
+        // below the number in the bottom right corner, once the cell is
+        // covered; a cell a line high has no room for it
+        if tile.get() != 0 && self.labels && cells.height > 1 && partial.map_or(true, |ratio| ratio >= 1.0) {
+            let label = format!("^{}", tile.get().trailing_zeros());
+            let x = x_coord + cells.width - label.len();
+            self.draw_text(x, y_coord + cells.height - 1, label, text_colour, tile_colour);
+        }
+    }
+
+    fn clear(&self) {
+        self.rustbox.clear();
+        self.score_width.set(0);
+    }
+
+    fn present(&self) {
+        if !self.fits() {
+            self.rustbox.clear();
+            self.draw_text(0, 0, "Terminal too small".to_string(), Color::Red, Color::Black);
+        }
+        self.rustbox.present();
+    }
+
+    fn beep(&self) {
+        // termbox has no bell, but the terminal takes one alongside its output
+        write_escape("\x07");
+    }
+
+    fn draw_lost(&self) {
+        let y = self.banner_row();
+        self.draw_centred(y, "You lost!".to_string(), Color::Red, Color::Black);
+    }
+
+    fn draw_time_up(&self) {
+        let y = self.banner_row();
+        self.draw_centred(y, "Time's up!".to_string(), Color::Red, Color::Black);
+    }
+
+    fn draw_won(&self) {
+        let y = self.banner_row();
+        self.draw_centred(y, "You won!".to_string(), Color::Green, Color::Black);
+        self.draw_centred(y + 1, "c to keep going".to_string(), Color::Green, Color::Black);
+    }
+
+    fn draw_paused(&self) {
+        let y = self.banner_row();
+        self.draw_centred(y, "Paused".to_string(), Color::Yellow, Color::Black);
+    }
+
+    fn draw_continuing(&self) {
+        let y = self.footer_row(1);
+        self.draw_centred(y, "Still going...".to_string(), Color::Green, Color::Black);
+    }
+
+    fn draw_score(&self, text: String) {
+        let x = self.x_origin.get() + self.cells.gap_x;
+        self.score_width.set(self.score_width.get().max(text.chars().count()));
+        self.draw_text(x, self.y_origin.get() + 1, text, Color::White, Color::Black);
+    }
+
+    fn draw_high_score(&self, text: String) {
+        // right aligned with the board, away from the score
+        let right = self.x_origin.get() + self.board_width() - self.cells.gap_x;
+        let width = text.chars().count();
+        let x = right.saturating_sub(width);
+        if self.stacked {
+            self.score_width.set(self.score_width.get().max(width));
+            self.draw_text(x, self.y_origin.get() + 2, text, Color::White, Color::Black);
+        } else if x > self.x_origin.get() + self.cells.gap_x + self.score_width.get() {
+            // left out rather than drawn over a long score
+            self.draw_text(x, self.y_origin.get() + 1, text, Color::White, Color::Black);
+        }
+    }
+
+    fn draw_instructions(&self, text: String) {
+        let y = self.footer_row(0);
+        self.draw_centred(y, text, Color::White, Color::Black);
+    }
+
+    fn select_panel(&self, panel: usize) {
+        self.panel.set(panel.min(self.panels - 1));
+        self.place();
+    }
+
+    fn draw_title(&self, text: String) {
+        if !self.title || *self.shown_title.borrow() == text {
+            return;
+        }
+        // terminals that don't know the sequence skip it
+        write_escape(&format!("\x1b]0;{}\x07", text));
+        *self.shown_title.borrow_mut() = text;
+    }
+
+    fn draw_stats(&self, text: String) {
+        let x = self.x_origin.get() + self.cells.gap_x;
+        // side by side, the left board's line would run into the right board
+        let text = if self.panels > 1 || self.stacked {
+            text.chars().take(self.board_width().saturating_sub(2 * self.cells.gap_x)).collect()
+        } else {
+            text
+        };
+        self.draw_text(x, self.y_origin.get(), text, Color::White, Color::Black);
+    }
+
+    fn draw_hint(&self, text: String) {
+        let y = self.footer_row(2);
+        self.draw_centred(y, text, Color::Yellow, Color::Black);
+    }
+
+    fn draw_debug(&self, grid: &[Vec<Tile>]) {
+        let cells = self.cells;
+        let left = self.x_origin.get();
+        let top = self.board_top();
+        for (x, column) in grid.iter().enumerate() {
+            let cell_x = left + cells.cell_origin(x, 0).0;
+            // column numbers in the gutter above, row numbers in the one on the left
+            self.draw_text(cell_x + cells.width / 2, top, x.to_string(), Color::Yellow, Color::Black);
+            for (y, tile) in column.iter().enumerate() {
+                let cell_y = top + cells.cell_origin(x, y).1;
+                if x == 0 {
+                    self.draw_text(left, cell_y + cells.height / 2, y.to_string(), Color::Yellow, Color::Black);
+                }
+                if tile.is_pending() {
+                    self.draw_text(cell_x, cell_y, "p".to_string(), Color::Yellow, Color::Black);
+                }
+            }
+        }
+    }
+
+    fn draw_cursor(&self, col: usize, row: usize) {
+        // in the gutters either side of the cell
+        let (x, y) = self.cells.cell_origin(col, row);
+        let x = self.x_origin.get() + x;
+        let y = self.board_top() + y + self.cells.height / 2;
+        self.draw_text(x - 1, y, "▶".to_string(), Color::Yellow, Color::Black);
+        self.draw_text(x + self.cells.width, y, "◀".to_string(), Color::Yellow, Color::Black);
+    }
+
+    fn draw_wall(&self, col: usize, row: usize) {
+        let (x, y) = self.cells.cell_origin(col, row);
+        let x = self.x_origin.get() + x;
+        let y = self.board_top() + y;
+        let (fill, fg) = if self.plain { ('#', Color::Default) } else { ('▒', Color::White) };
+        for dy in 0..self.cells.height {
+            for dx in 0..self.cells.width {
+                self.rustbox.print_char(x + dx, y + dy, rustbox::RB_NORMAL, fg, Color::Default, fill);
+            }
+        }
+    }
+
+    fn draw_summary(&self, summary: &Summary) {
+        let secs = summary.time.as_secs();
+        let mut lines = vec![
+            if summary.state == State::Won { "You won!" } else { "Game over" }.to_string(),
+            if summary.start_score > 0 {
+                format!("Score: {} ({} to start)", summary.score, summary.start_score)
+            } else {
+                format!("Score: {}", summary.score)
+            },
+            format!("Moves: {}", summary.moves),
+            format!("Time: {}:{:02}", secs / 60, secs % 60),
+            format!("Best tile: {}", summary.max_tile),
+        ];
+        if let Some(scores) = summary.player_scores {
+            lines.push(format!("P1: {}  P2: {}", scores[0], scores[1]));
+            lines.push(match summary.loser {
+                Some(player) => format!("Player {} wins", 2 - player),
+                None => "A draw".to_string(),
+            });
+        }
+        if let Some(percent) = summary.optimal_percent {
+            lines.push(format!("Optimal moves: {}%", percent));
+        }
+        if summary.new_high_score {
+            lines.push("New high score!".to_string());
+        }
+        lines.push("r to restart, v to watch again, q to quit".to_string());
+
+        let width = lines.iter().map(|line| line.chars().count()).max().unwrap_or(0) + 4;
+        let height = lines.len() + 2;
+        let x = self.x_origin.get() + self.board_width().saturating_sub(width) / 2;
+        let y = self.board_top() + self.board_height().saturating_sub(height) / 2;
+        self.fill_area(x, y, width, height, Color::White, Color::Black);
+        for (i, line) in lines.into_iter().enumerate() {
+            self.draw_centred(y + 1 + i, line, Color::White, Color::Black);
+        }
+    }
+
+    fn draw_help(&self, keys: &[Key], notes: &[String]) {
+        let mut lines: Vec<String> = keys.iter()
+            .map(|&key| format!("{:<9} {}", action_name(key), self.keys.describe(key)))
+            .collect();
+        lines.push(String::new());
+        lines.extend(notes.iter().cloned());
+        lines.push("any key to go back".to_string());
+
+        // left aligned in a box over the board, from the top if it is taller
+        let width = lines.iter().map(|line| line.chars().count()).max().unwrap_or(0) + 4;
+        let height = lines.len() + 2;
+        let x = self.x_origin.get() + self.board_width().saturating_sub(width) / 2;
+        let y = self.board_top() + self.board_height().saturating_sub(height) / 2;
+        self.fill_area(x, y, width, height, Color::White, Color::Black);
+        for (i, line) in lines.into_iter().enumerate() {
+            self.draw_text(x + 2, y + 1 + i, line, Color::White, Color::Black);
+        }
+    }
+}
+
+/// Actions that can be bound to characters, and the key the game acts on for each
+const ACTIONS: [(&str, Key); 23] = [
+    ("up", Key::Up),
+    ("down", Key::Down),
+    ("left", Key::Left),
+    ("right", Key::Right),
+    ("up-left", Key::Char('7')),
+    ("up-right", Key::Char('9')),
+    ("down-left", Key::Char('1')),
+    ("down-right", Key::Char('3')),
+    ("quit", Key::Char('q')),
+    ("undo", Key::Char('u')),
+    ("restart", Key::Char('r')),
+    ("continue", Key::Char('c')),
+    ("pause", Key::Char('p')),
+    ("debug", Key::Char('`')),
+    ("hint", Key::Char('?')),
+    ("clear", Key::Char('x')),
+    ("shuffle", Key::Char('m')),
+    ("remove", Key::Char('e')),
+    ("help", Key::Char('/')),
+    ("save", Key::Char('>')),
+    ("load", Key::Char('<')),
+    ("watch", Key::Char('v')),
+    ("preview", Key::Char('=')),
+];
+
+/// Name of the action a game key performs
+fn action_name(key: Key) -> &'static str {
+    ACTIONS.iter().find(|&&(_, k)| k == key).map(|&(name, _)| name).unwrap_or("?")
+}
+
+/// Which characters trigger which game keys, the arrow keys always move as well
+#[derive(Clone)]
+struct KeyBindings {
+    chars: HashMap<char, Key>,
+}
+
+/// The config file, keys maps action names to the characters bound to them
+#[derive(Deserialize)]
+struct ConfigFile {
+    #[serde(default)]
+    keys: HashMap<String, String>,
+}
+
+impl Default for KeyBindings {
+    /// WASD and hjkl move, space pauses, everything else is bound to its own letter
+    fn default() -> KeyBindings {
+        let mut chars = HashMap::new();
+        for &(c, key) in &[('w', Key::Up), ('k', Key::Up),
+                           ('s', Key::Down), ('j', Key::Down),
+                           ('a', Key::Left), ('h', Key::Left),
+                           ('d', Key::Right), ('l', Key::Right),
+                           (' ', Key::Char('p'))] {
+            chars.insert(c, key);
+        }
+        for &(_, key) in ACTIONS.iter() {
+            if let Key::Char(c) = key {
+                chars.insert(c, key);
+            }
+        }
+        KeyBindings { chars: chars }
+    }
+}
+
+impl KeyBindings {
+    /// Reads bindings from a TOML config file, such as
+    ///
+    /// ```toml
+    /// [keys]
+    /// up = "wk"
+    /// quit = "x"
+    /// ```
+    ///
+    /// Every action listed loses its default characters, the others keep theirs.
+    fn parse(text: &str) -> Result<KeyBindings, String> {
+        let config: ConfigFile = toml::from_str(text).map_err(|e| e.to_string())?;
+
+        let mut rebound = Vec::new();
+        for (action, chars) in &config.keys {
+            let key = match ACTIONS.iter().find(|&&(name, _)| name == action) {
+                Some(&(_, key)) => key,
+                None => return Err(format!("unknown action: {}", action)),
+            };
+            if chars.is_empty() {
+                return Err(format!("no key given for {}", action));
+            }
+            rebound.push((key, chars));
+        }
+
+        let mut bindings = KeyBindings::default();
+        bindings.chars.retain(|_, key| !rebound.iter().any(|&(k, _)| k == *key));
+        for &(key, chars) in &rebound {
+            for c in chars.chars() {
+                match bindings.chars.insert(c, key) {
+                    Some(other) if other != key => {
+                        return Err(format!("'{}' is bound to both {} and {}",
+                                           c, action_name(other), action_name(key)));
+                    }
+                    _ => {}
+                }
+            }
+        }
+        Ok(bindings)
+    }
+
+    /// Binds `c` to the action of `key` instead of its current characters
+    fn bind(&mut self, key: Key, c: char) -> Result<(), String> {
+        match self.chars.get(&c) {
+            Some(&other) if other != key => {
+                return Err(format!("'{}' is already bound to {}", c, action_name(other)));
+            }
+            _ => {}
+        }
+        self.chars.retain(|_, k| *k != key);
+        self.chars.insert(c, key);
+        Ok(())
+    }
+
+    /// Passes w, a, s and d on as themselves for a race to move the left
+    /// board with, the arrows move the right one
+    fn split_for_race(&mut self) {
+        // any other character moving would be ambiguous
+        self.chars.retain(|_, key| match *key {
+            Key::Up | Key::Down | Key::Left | Key::Right => false,
+            Key::Char(_) => true,
+        });
+        for c in "wasd".chars() {
+            self.chars.insert(c, Key::Char(c));
+        }
+    }
+
+    /// Everything that triggers `key`: the characters bound to it in order,
+    /// then the arrow or function key that always does
+    fn describe(&self, key: Key) -> String {
+        let mut chars: Vec<char> = self.chars.iter()
+            .filter(|&(_, &k)| k == key)
+            .map(|(&c, _)| c)
+            .collect();
+        chars.sort();
+        let mut names: Vec<String> = chars.into_iter()
+            .map(|c| if c == ' ' { "space".to_string() } else { c.to_string() })
+            .collect();
+        match key {
+            Key::Up => names.push("↑".to_string()),
+            Key::Down => names.push("↓".to_string()),
+            Key::Left => names.push("←".to_string()),
+            Key::Right => names.push("→".to_string()),
+            Key::Char('/') => names.push("F1".to_string()),
+            Key::Char('>') => names.push("F5".to_string()),
+            Key::Char('<') => names.push("F9".to_string()),
+            Key::Char('=') => names.push("Tab".to_string()),
+            Key::Char(_) => {}
+        }
+        names.join(" ")
+    }
+
+    fn load(path: &PathBuf) -> Result<KeyBindings, String> {
+        let text = fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        KeyBindings::parse(&text).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    /// Translates a termbox key into a game key
+    fn map(&self, key: RKey) -> Option<Key> {
+        match key {
+            RKey::Up => Some(Key::Up),
+            RKey::Down => Some(Key::Down),
+            RKey::Left => Some(Key::Left),
+            RKey::Right => Some(Key::Right),
+            RKey::F(1) => Some(Key::Char('/')),
+            RKey::F(5) => Some(Key::Char('>')),
+            RKey::F(9) => Some(Key::Char('<')),
+            RKey::Tab => Some(Key::Char('=')),
+            // the terminal is raw, so Ctrl-C comes as a key rather than a signal
+            RKey::Ctrl('c') => Some(Key::Char('q')),
+            RKey::Char(c) => self.chars.get(&c).cloned(),
+            _ => None,
+        }
+    }
+}
+
+impl<'a> TermboxUI<'a> {
+    fn new(rustbox: &'a rustbox::RustBox, size: BoardSize, cells: CellLayout,
+           theme: Theme, keys: KeyBindings) -> TermboxUI<'a> {
+        let board = cells.board_colours(size, &theme);
+        let ui = TermboxUI {
+            rustbox: rustbox,
+            keys: keys,
+            theme: theme,
+            size: size,
+            cells: cells,
+            board: board,
+            x_origin: Cell::new(0),
+            y_origin: Cell::new(0),
+            panels: 1,
+            panels_left: Cell::new(0),
+            panel: Cell::new(0),
+            plain: false,
+            labels: false,
+            abbrev: false,
+            title: false,
+            stacked: false,
+            score_width: Cell::new(0),
+            shown_title: RefCell::new(String::new()),
+            error: RefCell::new(None),
+            drag: Cell::new(None),
+        };
+        ui.place();
+        ui
+    }
+
+    /// Turns mouse events into moves: the wheel moves up or down, and a drag
+    /// with the left button moves the way it went furthest
+    fn swipe(&self, button: rustbox::Mouse, x: i32, y: i32) -> Option<Key> {
+        let (x0, y0) = match button {
+            rustbox::Mouse::WheelUp => return Some(Key::Up),
+            rustbox::Mouse::WheelDown => return Some(Key::Down),
+            rustbox::Mouse::Left => match self.drag.get() {
+                Some((x0, y0, seen)) if seen.elapsed() < Duration::from_millis(DRAG_GAP_MS) => (x0, y0),
+                _ => {
+                    self.drag.set(Some((x, y, Instant::now())));
+                    return None;
+                }
+            },
+            _ => return None,
+        };
+        // a row is about as tall as two columns are wide
+        let (dx, dy) = (x - x0, 2 * (y - y0));
+        if dx.abs() < DRAG_COLUMNS && dy.abs() < DRAG_COLUMNS {
+            self.drag.set(Some((x0, y0, Instant::now())));
+            return None;
+        }
+        self.drag.set(None);
+        Some(match (dx.abs() >= dy.abs(), dx > 0, dy > 0) {
+            (true, true, _) => Key::Right,
+            (true, false, _) => Key::Left,
+            (false, _, true) => Key::Down,
+            (false, _, false) => Key::Up,
+        })
+    }
+
+    fn board_width(&self) -> usize {
+        self.cells.board_width(self.size)
+    }
+
+    fn board_height(&self) -> usize {
+        self.cells.board_height(self.size)
+    }
+
+    /// Lines above the board, one more when the best score is stacked
+    fn header_lines(&self) -> usize {
+        if self.stacked { HEADER_LINES + 1 } else { HEADER_LINES }
+    }
+
+    /// Height of everything drawn
+    fn layout_height(&self) -> usize {
+        self.header_lines() + self.board_height() + FOOTER_LINES
+    }
+
+    /// Row of the top edge of the board
+    fn board_top(&self) -> usize {
+        self.y_origin.get() + self.header_lines()
+    }
+
+    /// Row of the nth line below the board
+    fn footer_row(&self, n: usize) -> usize {
+        self.board_top() + self.board_height() + n
+    }
+
+    /// Columns taken by all the panels and the gaps between them
+    fn layout_width(&self) -> usize {
+        self.board_width() * self.panels + PANEL_GAP * (self.panels - 1)
+    }
+
+    /// Whether everything drawn fits in the terminal, and the scores on the board
+    fn fits(&self) -> bool {
+        self.rustbox.width() >= self.layout_width()
+            && self.rustbox.height() >= self.layout_height()
+            && self.score_width.get() <= self.board_width().saturating_sub(2 * self.cells.gap_x)
+    }
+
+    /// Centres the layout in the terminal at its current size
+    fn place(&self) {
+        let width = self.rustbox.width();
+        let height = self.rustbox.height();
+        self.panels_left.set(width.saturating_sub(self.layout_width()) / 2);
+        self.x_origin.set(self.panels_left.get() + self.panel.get() * (self.board_width() + PANEL_GAP));
+        self.y_origin.set(height.saturating_sub(self.layout_height()) / 2);
+    }
+
+    /// Row of the win and loss banners, the middle of the board
+    fn banner_row(&self) -> usize {
+        self.board_top() + self.board_height() / 2
+    }
+
+    /// The board as ASCII: a frame, and empty brackets where the cells are
+    fn draw_plain_bg(&self, x_offset: usize, y_offset: usize) {
+        let left = self.x_origin.get() + x_offset;
+        let top = self.board_top() + y_offset;
+        let width = self.board_width();
+        let height = self.board_height();
+        let print = |x, y, ch| {
+            self.rustbox.print_char(left + x, top + y, rustbox::RB_NORMAL,
+                                    Color::Default, Color::Default, ch);
+        };
+        for x in 0..width {
+            let ch = if x == 0 || x == width - 1 { '+' } else { '-' };
+            print(x, 0, ch);
+            print(x, height - 1, ch);
+        }
+        for y in 1..height - 1 {
+            print(0, y, '|');
+            print(width - 1, y, '|');
+        }
+
+        let empty = format!("[{}]", " ".repeat(self.cells.width - 2));
+        for i in 0..self.size.cols {
+            for j in 0..self.size.rows {
+                let (x, y) = self.cells.cell_origin(i, j);
+                let x = left + x;
+                let y = top + y + self.cells.height / 2;
+                self.draw_text(x, y, empty.clone(), Color::Default, Color::Default);
+            }
+        }
+    }
+
+    /// Colours to draw with, the terminal's defaults in plain mode
+    fn colours(&self, fg: Color, bg: Color) -> (Color, Color) {
+        if self.plain {
+            (Color::Default, Color::Default)
+        } else {
+            (fg, bg)
+        }
+    }
+
+    /// Draws text centred over the board
+    fn draw_centred(&self, y: usize, line: String, fg: Color, bg: Color) {
+        let width = line.chars().count();
+        let x = self.x_origin.get() + self.board_width().saturating_sub(width) / 2;
+        self.draw_text(x, y, line, fg, bg);
+    }
+
+    fn fill_area(&self, x: usize, y: usize, w: usize, h: usize, fg: Color, bg: Color) {
+        let (fg, bg) = self.colours(fg, bg);
+        for row in 0..h {
+            for column in 0..w {
+                self.rustbox.print_char(x + column, y + row, rustbox::RB_NORMAL, fg, bg, ' ');
+            }
+        }
+    }
+
+    fn draw_rectangle(&self,
+                      x: usize,
+                      y: usize,
+                      w: usize,
+                      h: usize,
+                      fill: Color,
+    ) {
+        self.fill_area(x, y, w, h, fill, fill);
+    }
+
+    fn draw_text(&self, x: usize, y: usize, line: String, fg: Color, bg: Color) -> (usize, usize) {
+        let (fg, bg) = self.colours(fg, bg);
+        for (i, ch) in line.chars().enumerate() {
+            self.rustbox.print_char(x + i, y, rustbox::RB_NORMAL, fg, bg, ch);
+        }
+        (x + line.len(), y)
+    }
+}
+
+/// Where text `len` characters long starts to sit centred in `width` columns,
+/// an odd leftover column goes on the left; 0 when the text doesn't fit
+fn centred_offset(width: usize, len: usize) -> usize {
+    (width.saturating_sub(len) + 1) / 2
+}
+
+/// Sends a control sequence straight to the terminal, past termbox's buffer
+fn write_escape(sequence: &str) {
+    let mut stdout = io::stdout();
+    let _ = stdout.write_all(sequence.as_bytes());
+    let _ = stdout.flush();
+}
+
+/// Quits the game on SIGINT and SIGTERM instead of leaving the terminal raw
+#[cfg(unix)]
+fn catch_interrupts() {
+    const SIGINT: i32 = 2;
+    const SIGTERM: i32 = 15;
+    extern "C" fn interrupt(_signal: i32) {
+        INTERRUPTED.store(true, Ordering::SeqCst);
+    }
+    extern "C" {
+        fn signal(signal: i32, handler: extern "C" fn(i32)) -> usize;
+    }
+    // only stores a flag, which is safe in a signal handler
+    unsafe {
+        signal(SIGINT, interrupt);
+        signal(SIGTERM, interrupt);
+    }
+}
+
+#[cfg(not(unix))]
+fn catch_interrupts() {}
+
+/// Puts back the terminal's title when dropped, however playing ends
+struct TitleGuard;
+
+impl TitleGuard {
+    fn push() -> TitleGuard {
+        // saved on the terminal's title stack, where it has one
+        write_escape("\x1b[22;0t");
+        TitleGuard
+    }
+}
+
+impl Drop for TitleGuard {
+    fn drop(&mut self) {
+        // cleared in case the stack isn't there to restore it
+        write_escape("\x1b]0;\x07\x1b[23;0t");
+    }
+}
+
+/// Guesses from the environment whether the terminal shows 256 colours
+fn detect_colors() -> u16 {
+    let term = env::var("TERM").unwrap_or_default();
+    if term.contains("256") || env::var_os("COLORTERM").is_some() {
+        256
+    } else {
+        16
+    }
+}
+
+/// The default config file of a profile
+fn config_path(profile: &str) -> Option<PathBuf> {
+    let config_home = match env::var_os("XDG_CONFIG_HOME") {
+        Some(dir) => PathBuf::from(dir),
+        None => PathBuf::from(env::var_os("HOME")?).join(".config"),
+    };
+    Some(profile_dir(config_home, profile).join("config.toml"))
+}
+
+/// Sets up the terminal from the options and plays in it
+pub fn play_in_terminal<F: FnOnce(&UI)>(options: &Options, size: BoardSize, play: F) -> Result<(), String> {
+    // the default config file is optional, one given with --config is not
+    let mut keys = match options.config.clone().or_else(|| config_path(&options.profile).filter(|path| path.exists())) {
+        Some(path) => KeyBindings::load(&path)?,
+        None => KeyBindings::default(),
+    };
+    if let Some(c) = options.hint_key {
+        keys.bind(Key::Char('?'), c)?;
+    }
+    if options.race {
+        keys.split_for_race();
+    }
+
+    let init = |output_mode| RustBox::init(
+        rustbox::InitOptions {
+            // terminals without mouse support just never send mouse events
+            input_mode: if options.mouse { rustbox::InputMode::EscMouse } else { rustbox::InputMode::Current },
+            output_mode: output_mode,
+            buffer_stderr: true,
+        });
+    // plain mode has no use for more than the default colours
+    let colors = if options.no_color { 16 } else { options.colors.unwrap_or_else(detect_colors) };
+    let (rustbox, colors) = match colors {
+        256 => match init(rustbox::OutputMode::EightBit) {
+            Ok(rustbox) => (Ok(rustbox), 256),
+            Err(_) => (init(rustbox::OutputMode::Normal), 16),
+        },
+        _ => (init(rustbox::OutputMode::Normal), 16),
+    };
+    let rustbox = rustbox.map_err(|e| match e {
+        rustbox::InitError::FailedToOpenTTY => {
+            "can't open the terminal, is this a tty? --headless plays without one".to_string()
+        }
+        e => format!("can't set up the terminal: {}", e),
+    })?;
+
+    let mut theme = Theme::by_name(&options.theme).unwrap_or_else(Theme::classic);
+    if colors == 16 && !theme.is_basic() {
+        theme = Theme::sixteen();
+    }
+    if let Some(bg) = options.bg {
+        theme.background = Color::Byte(bg);
+    }
+    if let Some(empty) = options.empty {
+        theme.empty = Color::Byte(empty);
+    }
+    if theme.background == theme.empty {
+        return Err("empty cells need a colour of their own, apart from the background".to_string());
+    }
+    let cells = if options.compact { COMPACT_CELLS } else { NORMAL_CELLS };
+    let mut ui = TermboxUI::new(&rustbox, size, cells, theme, keys);
+    ui.plain = options.no_color;
+    ui.labels = options.labels;
+    ui.abbrev = options.abbrev;
+    ui.stacked = options.compact;
+    if options.race {
+        ui.panels = 2;
+    }
+    ui.place();
+    // dropped after play, even when it panics, and before rustbox
+    let _title = if options.set_title {
+        ui.title = true;
+        Some(TitleGuard::push())
+    } else {
+        None
+    };
+    catch_interrupts();
+    play(&ui);
+    // the terminal is restored when rustbox is dropped, before the caller prints this
+    match ui.error.into_inner() {
+        Some(e) => Err(e),
+        None => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn letters_move_and_actions_keep_their_own() {
+        let keys = KeyBindings::default();
+        for &(c, key) in &[('w', Key::Up), ('k', Key::Up), ('s', Key::Down), ('j', Key::Down),
+                           ('a', Key::Left), ('h', Key::Left), ('d', Key::Right), ('l', Key::Right)] {
+            assert_eq!(keys.map(RKey::Char(c)), Some(key), "'{}'", c);
+        }
+        // an action on a moving letter would take it over
+        for &(name, key) in ACTIONS.iter() {
+            if let Key::Char(c) = key {
+                assert_eq!(keys.map(RKey::Char(c)), Some(key), "{} on '{}'", name, c);
+            }
+        }
+        assert_eq!(keys.map(RKey::Char(' ')), Some(Key::Char('p')));
+        assert_eq!(keys.map(RKey::Ctrl('c')), Some(Key::Char('q')));
+        assert_eq!(keys.map(RKey::Tab), Some(Key::Char('=')));
+        assert_eq!(keys.map(RKey::Char('z')), None);
+    }
+
+    #[test]
+    fn readme_keys_parse_to_the_defaults() {
+        let readme = include_str!("../README.md");
+        let start = readme.find("    [keys]\n").expect("a [keys] sample in the README");
+        let sample: String = readme[start..].lines()
+            .take_while(|line| !line.is_empty())
+            .map(|line| format!("{}\n", line.trim()))
+            .collect();
+        let keys = KeyBindings::parse(&sample).unwrap();
+        assert_eq!(keys.chars, KeyBindings::default().chars);
+    }
+
+    #[test]
+    fn clashes_are_refused() {
+        assert!(KeyBindings::parse("[keys]\nundo = \"s\"\ndown = \"sj\"\n").is_err());
+        assert!(KeyBindings::parse("[keys]\nfly = \"f\"\n").is_err());
+        let mut keys = KeyBindings::default();
+        assert!(keys.bind(Key::Char('u'), 'w').is_err());
+        assert!(keys.bind(Key::Char('u'), 'z').is_ok());
+        assert_eq!(keys.map(RKey::Char('z')), Some(Key::Char('u')));
+        assert_eq!(keys.map(RKey::Char('u')), None);
+    }
+
+    #[test]
+    fn tile_text_is_centred_in_the_cell() {
+        let offsets: Vec<usize> = (1..8).map(|len| centred_offset(6, len)).collect();
+        assert_eq!(offsets, vec![3, 2, 2, 1, 1, 0, 0]);
+        for width in 1..10 {
+            for len in 1..width + 1 {
+                let offset = centred_offset(width, len);
+                assert!(offset + len <= width, "{} in {}", len, width);
+                // never more than a column off the middle
+                assert!(offset >= width - len - offset && offset <= width - len - offset + 1);
+            }
+        }
+    }
+
+    #[test]
+    fn every_tile_text_shows_on_its_fill() {
+        let themes = [Theme::classic(), Theme::mono(), Theme::sixteen(), Theme::high_contrast()];
+        for (i, theme) in themes.iter().enumerate() {
+            for power in 1..24 {
+                let (fg, bg) = theme.tile_colours(1 << power);
+                assert!(fg != bg, "theme {}, tile {}", i, 1 << power);
+            }
+        }
+    }
+
+    #[test]
+    fn board_takes_the_theme_colours() {
+        let mut theme = Theme::classic();
+        theme.background = Color::Byte(17);
+        theme.empty = Color::Byte(250);
+        let size = BoardSize { cols: 3, rows: 2 };
+        let board = NORMAL_CELLS.board_colours(size, &theme);
+        assert_eq!(board.len(), NORMAL_CELLS.board_width(size));
+        assert!(board.iter().all(|column| column.len() == NORMAL_CELLS.board_height(size)));
+        let empty = board.iter().flat_map(|column| column.iter()).filter(|&&c| c == theme.empty).count();
+        assert_eq!(empty, 6 * NORMAL_CELLS.width * NORMAL_CELLS.height);
+        // the corner is gutter, the first cell starts past it
+        assert!(board[0][0] == theme.background);
+        assert!(board[NORMAL_CELLS.gap_x][NORMAL_CELLS.gap_y] == theme.empty);
+        assert!(board.iter().flat_map(|column| column.iter()).all(|&c| c == theme.empty || c == theme.background));
+    }
+}
@@ -0,0 +1,431 @@
+//! What the game needs from a front end
+
+use std::cell::{Cell, RefCell};
+use std::fmt;
+
+use board::{BoardSize, State, Tile};
+use game::Summary;
+use record::key_name;
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum Key {
+    Right,
+    Left,
+    Up,
+    Down,
+    Char(char),
+}
+
+pub trait UI {
+    fn wait_key(&self, Option<u64>) -> Option<Key>;
+    /// Draws the empty board, offset from where the layout puts it
+    fn draw_bg(&self, x_offset: usize, y_offset: usize);
+    fn draw_grid(&self, grid: &[Vec<Tile>]);
+    /// Draws, fainter than the tiles, the board a move would leave
+    fn draw_preview(&self, grid: &[Vec<Tile>]);
+    fn draw_tile(&self, col: usize, row: usize, tile: Tile, partial: Option<f32>);
+    fn draw_tile_at(&self, tile: Tile, x_coord: usize, y_coord: usize, partial: Option<f32>);
+    /// Draws a tile part of the way between cells, used while it slides
+    fn draw_tile_between(&self, tile: Tile, col: f32, row: f32, partial: Option<f32>);
+    fn clear(&self);
+    fn present(&self);
+    /// Rings the terminal bell, without waiting for it
+    fn beep(&self);
+    fn draw_lost(&self);
+    /// Drawn instead of `draw_lost` when a blitz game runs out of time
+    fn draw_time_up(&self);
+    fn draw_won(&self);
+    fn draw_continuing(&self);
+    fn draw_paused(&self);
+    fn draw_score(&self, text: String);
+    fn draw_high_score(&self, text: String);
+    fn draw_instructions(&self, text: String);
+    /// Draws on one of the boards shown side by side from now on, see `Race`
+    fn select_panel(&self, panel: usize);
+    /// Puts a short status outside the game, such as in the window title
+    fn draw_title(&self, text: String);
+    fn draw_stats(&self, text: String);
+    fn draw_hint(&self, text: String);
+    /// Drawn over the board once the game is over
+    fn draw_summary(&self, summary: &Summary);
+    /// Numbers the columns and rows, and marks pending (p) tiles
+    fn draw_debug(&self, grid: &[Vec<Tile>]);
+    /// Highlights the cell picked for removal
+    fn draw_cursor(&self, col: usize, row: usize);
+    /// Fills a cell no tile can enter
+    fn draw_wall(&self, col: usize, row: usize);
+    /// Drawn over the board: what each of `keys` is bound to, then `notes`
+    /// on the modes and rules of the game
+    fn draw_help(&self, keys: &[Key], notes: &[String]);
+}
+
+/// A tile's value short enough for a small cell: as it is below 10000, then in
+/// thousands such as 16k for 16384, and from a million on in millions
+///
+/// Never more than 4 characters below a billion. Only for tiles, scores are
+/// always shown in full.
+pub fn abbreviate(value: usize) -> String {
+    if value < 10_000 {
+        value.to_string()
+    } else if value < 1_000_000 {
+        format!("{}k", value / 1000)
+    } else {
+        format!("{}m", value / 1_000_000)
+    }
+}
+
+/// A UI that draws nothing and takes its keys from an iterator, for scripted play
+pub struct NullUI {
+    pub keys: RefCell<Box<Iterator<Item = Key>>>,
+}
+
+impl NullUI {
+    pub fn new<I: Iterator<Item = Key> + 'static>(keys: I) -> NullUI {
+        NullUI {
+            keys: RefCell::new(Box::new(keys)),
+        }
+    }
+}
+
+impl UI for NullUI {
+    fn wait_key(&self, _timeout: Option<u64>) -> Option<Key> {
+        // quit once the keys run out
+        Some(self.keys.borrow_mut().next().unwrap_or(Key::Char('q')))
+    }
+
+    fn draw_bg(&self, _x_offset: usize, _y_offset: usize) {}
+    fn draw_grid(&self, _grid: &[Vec<Tile>]) {}
+    fn draw_preview(&self, _grid: &[Vec<Tile>]) {}
+    fn draw_tile(&self, _col: usize, _row: usize, _tile: Tile, _partial: Option<f32>) {}
+    fn draw_tile_at(&self, _tile: Tile, _x_coord: usize, _y_coord: usize, _partial: Option<f32>) {}
+    fn draw_tile_between(&self, _tile: Tile, _col: f32, _row: f32, _partial: Option<f32>) {}
+    fn clear(&self) {}
+    fn present(&self) {}
+    fn beep(&self) {}
+    fn draw_lost(&self) {}
+    fn draw_time_up(&self) {}
+    fn draw_won(&self) {}
+    fn draw_continuing(&self) {}
+    fn draw_paused(&self) {}
+    fn draw_score(&self, _text: String) {}
+    fn draw_high_score(&self, _text: String) {}
+    fn draw_instructions(&self, _text: String) {}
+    fn select_panel(&self, _panel: usize) {}
+    fn draw_title(&self, _text: String) {}
+    fn draw_stats(&self, _text: String) {}
+    fn draw_hint(&self, _text: String) {}
+    fn draw_summary(&self, _summary: &Summary) {}
+    fn draw_debug(&self, _grid: &[Vec<Tile>]) {}
+    fn draw_cursor(&self, _col: usize, _row: usize) {}
+    fn draw_wall(&self, _col: usize, _row: usize) {}
+    fn draw_help(&self, _keys: &[Key], _notes: &[String]) {}
+}
+
+/// Columns a cell takes in a `RecordingUI`, the number and a space before the next
+const RECORDED_CELL_WIDTH: usize = 6;
+/// Lines above the board in a `RecordingUI`, for the score and the best score
+const RECORDED_HEADER_LINES: usize = 2;
+
+/// A UI that draws into a grid of characters instead of a terminal, so that
+/// tests can check what a game shows
+///
+/// The score and the best score take the first two lines. The board follows
+/// with a line per row, each cell a number, or `.` when empty, right aligned
+/// in `RECORDED_CELL_WIDTH` columns. Below it come a line each for the game's
+/// state, the hint, the stats and the instructions. Every character keeps the
+/// value of the tile it belongs to, 0 for anything else, standing in for its
+/// colour. Keys come from an iterator, as with `NullUI`.
+pub struct RecordingUI {
+    pub keys: RefCell<Box<Iterator<Item = Key>>>,
+    size: BoardSize,
+    /// Lines of characters, each with the value of its tile
+    screen: RefCell<Vec<Vec<(char, usize)>>>,
+    /// Times `beep` was called
+    pub beeps: Cell<usize>,
+}
+
+impl RecordingUI {
+    pub fn new<I: Iterator<Item = Key> + 'static>(size: BoardSize, keys: I) -> RecordingUI {
+        RecordingUI {
+            keys: RefCell::new(Box::new(keys)),
+            size: size,
+            screen: RefCell::new(Vec::new()),
+            beeps: Cell::new(0),
+        }
+    }
+
+    /// A line of what was drawn, without trailing spaces
+    pub fn line(&self, y: usize) -> String {
+        self.screen.borrow().get(y)
+            .map(|line| line.iter().map(|&(c, _)| c).collect::<String>().trim_end().to_string())
+            .unwrap_or_default()
+    }
+
+    /// Value of the tile the character at (x, y) belongs to, 0 if none does
+    pub fn value_at(&self, x: usize, y: usize) -> usize {
+        self.screen.borrow().get(y).and_then(|line| line.get(x)).map_or(0, |&(_, value)| value)
+    }
+
+    /// Line of the board's top row
+    pub fn board_top(&self) -> usize {
+        RECORDED_HEADER_LINES
+    }
+
+    /// Line of the nth line below the board
+    fn footer_row(&self, n: usize) -> usize {
+        RECORDED_HEADER_LINES + self.size.rows + n
+    }
+
+    /// Writes text from (x, y) on, growing the screen to fit
+    fn put(&self, x: usize, y: usize, text: &str, value: usize) {
+        let mut screen = self.screen.borrow_mut();
+        if screen.len() <= y {
+            screen.resize(y + 1, Vec::new());
+        }
+        let line = &mut screen[y];
+        for (i, c) in text.chars().enumerate() {
+            if line.len() <= x + i {
+                line.resize(x + i + 1, (' ', 0));
+            }
+            line[x + i] = (c, value);
+        }
+    }
+
+    /// Replaces a whole line
+    fn put_line(&self, y: usize, text: &str) {
+        if let Some(line) = self.screen.borrow_mut().get_mut(y) {
+            line.clear();
+        }
+        self.put(0, y, text, 0);
+    }
+}
+
+impl fmt::Display for RecordingUI {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for y in 0..self.screen.borrow().len() {
+            writeln!(f, "{}", self.line(y))?;
+        }
+        Ok(())
+    }
+}
+
+impl UI for RecordingUI {
+    fn wait_key(&self, _timeout: Option<u64>) -> Option<Key> {
+        // quit once the keys run out
+        Some(self.keys.borrow_mut().next().unwrap_or(Key::Char('q')))
+    }
+
+    fn draw_bg(&self, x_offset: usize, y_offset: usize) {
+        for row in 0..self.size.rows {
+            for col in 0..self.size.cols {
+                let x = x_offset + col * RECORDED_CELL_WIDTH;
+                let text = format!("{:>1$}", ".", RECORDED_CELL_WIDTH - 1);
+                self.put(x, y_offset + RECORDED_HEADER_LINES + row, &text, 0);
+            }
+        }
+    }
+
+    fn draw_grid(&self, grid: &[Vec<Tile>]) {
+        for (col, column) in grid.iter().enumerate() {
+            for (row, &tile) in column.iter().enumerate() {
+                if tile.get() != 0 {
+                    self.draw_tile(col, row, tile, None);
+                }
+            }
+        }
+    }
+
+    fn draw_preview(&self, grid: &[Vec<Tile>]) {
+        for (col, column) in grid.iter().enumerate() {
+            for (row, &tile) in column.iter().enumerate() {
+                if tile.value() != 0 {
+                    // marked so it can't be taken for the board itself
+                    let text = format!("{:>1$}", format!("~{}", tile.value()), RECORDED_CELL_WIDTH - 1);
+                    self.put(col * RECORDED_CELL_WIDTH, RECORDED_HEADER_LINES + row, &text, tile.value());
+                }
+            }
+        }
+    }
+
+    fn draw_tile(&self, col: usize, row: usize, tile: Tile, _partial: Option<f32>) {
+        self.draw_tile_at(tile, col * RECORDED_CELL_WIDTH, RECORDED_HEADER_LINES + row, None);
+    }
+
+    fn draw_tile_at(&self, tile: Tile, x_coord: usize, y_coord: usize, _partial: Option<f32>) {
+        let text = format!("{:>1$}", tile.get(), RECORDED_CELL_WIDTH - 1);
+        self.put(x_coord, y_coord, &text, tile.get());
+    }
+
+    fn draw_tile_between(&self, tile: Tile, col: f32, row: f32, partial: Option<f32>) {
+        // characters can't be split, so a sliding tile sits in the nearest cell
+        self.draw_tile(col.round() as usize, row.round() as usize, tile, partial);
+    }
+
+    fn clear(&self) {
+        self.screen.borrow_mut().clear();
+    }
+
+    fn present(&self) {}
+
+    fn beep(&self) {
+        self.beeps.set(self.beeps.get() + 1);
+    }
+
+    fn draw_lost(&self) {
+        self.put_line(self.footer_row(0), "You lost!");
+    }
+
+    fn draw_time_up(&self) {
+        self.put_line(self.footer_row(0), "Time's up!");
+    }
+
+    fn draw_won(&self) {
+        self.put_line(self.footer_row(0), "You won! c to keep going");
+    }
+
+    fn draw_continuing(&self) {
+        self.put_line(self.footer_row(0), "Still going...");
+    }
+
+    fn draw_paused(&self) {
+        self.put_line(self.footer_row(0), "Paused");
+    }
+
+    fn draw_score(&self, text: String) {
+        self.put_line(0, &text);
+    }
+
+    fn draw_high_score(&self, text: String) {
+        self.put_line(1, &text);
+    }
+
+    fn draw_hint(&self, text: String) {
+        self.put_line(self.footer_row(1), &text);
+    }
+
+    fn draw_stats(&self, text: String) {
+        self.put_line(self.footer_row(2), &text);
+    }
+
+    fn draw_instructions(&self, text: String) {
+        self.put_line(self.footer_row(3), &text);
+    }
+
+    fn select_panel(&self, _panel: usize) {}
+
+    fn draw_title(&self, _text: String) {}
+
+    fn draw_summary(&self, summary: &Summary) {
+        let mut text = format!("{} Score: {} Moves: {} Best tile: {}",
+                               if summary.state == State::Won { "You won!" } else { "Game over" },
+                               summary.score, summary.moves, summary.max_tile);
+        if summary.start_score > 0 {
+            text.push_str(&format!(" Start score: {}", summary.start_score));
+        }
+        self.put_line(self.footer_row(0), &text);
+    }
+
+    fn draw_debug(&self, grid: &[Vec<Tile>]) {
+        for (col, column) in grid.iter().enumerate() {
+            for (row, tile) in column.iter().enumerate() {
+                if tile.is_pending() {
+                    self.put(col * RECORDED_CELL_WIDTH, RECORDED_HEADER_LINES + row, "p", tile.get());
+                }
+            }
+        }
+    }
+
+    fn draw_cursor(&self, col: usize, row: usize) {
+        self.put(col * RECORDED_CELL_WIDTH, RECORDED_HEADER_LINES + row, ">", 0);
+    }
+
+    fn draw_wall(&self, col: usize, row: usize) {
+        let text = format!("{:>1$}", "#", RECORDED_CELL_WIDTH - 1);
+        self.put(col * RECORDED_CELL_WIDTH, RECORDED_HEADER_LINES + row, &text, 0);
+    }
+
+    fn draw_help(&self, keys: &[Key], notes: &[String]) {
+        let keys: Vec<String> = keys.iter().map(|&key| key_name(key)).collect();
+        self.put_line(self.footer_row(0), &format!("Help: {}", keys.join(" ")));
+        self.put_line(self.footer_row(1), &notes.join("  "));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use board::Board;
+    use game::Game;
+
+    #[test]
+    fn abbreviates_at_the_boundaries() {
+        assert_eq!(abbreviate(0), "0");
+        assert_eq!(abbreviate(8192), "8192");
+        assert_eq!(abbreviate(9_999), "9999");
+        assert_eq!(abbreviate(10_000), "10k");
+        assert_eq!(abbreviate(16_384), "16k");
+        assert_eq!(abbreviate(131_072), "131k");
+        assert_eq!(abbreviate(999_999), "999k");
+        assert_eq!(abbreviate(1_000_000), "1m");
+        assert_eq!(abbreviate(1_048_576), "1m");
+        assert_eq!(abbreviate(999_999_999), "999m");
+        for power in 1..30 {
+            assert!(abbreviate(1 << power).len() <= 4, "{}", 1 << power);
+        }
+    }
+
+    #[test]
+    fn records_a_game() {
+        let ui = RecordingUI::new(BoardSize::default(), ::std::iter::empty());
+        let mut board = Board::new(BoardSize::default(), 1);
+        board.spawn_at((0, 0), 2).unwrap();
+        board.spawn_at((1, 0), 2).unwrap();
+        let mut game = Game::new(&ui, board);
+        game.animation_ms = 0;
+        game.start_tiles = 0;
+        game.start();
+        game.step(Key::Left);
+        ui.clear();
+        game.draw_frame();
+
+        assert_eq!(ui.line(0), "Score: 4");
+        assert_eq!(ui.line(1), "Best: 0");
+        let top = ui.board_top();
+        assert!(ui.line(top).starts_with("    4 "));
+        // the padding is coloured with its tile, the gap after it isn't
+        assert_eq!(ui.value_at(0, top), 4);
+        assert_eq!(ui.value_at(5, top), 0);
+        // the merged tile, and the one that came after the move
+        let tiles: usize = (top..top + 4)
+            .map(|y| ui.line(y).split_whitespace().filter(|&cell| cell != ".").count())
+            .sum();
+        assert_eq!(tiles, 2);
+        assert!(ui.line(ui.footer_row(2)).starts_with("Moves: 1 "));
+    }
+
+    #[test]
+    fn draws_a_wide_board() {
+        let size = BoardSize { cols: 5, rows: 3 };
+        let ui = RecordingUI::new(size, ::std::iter::empty());
+        let board = Board::from_text("2 0 0 0 4\n0 0 8 0 0\n16 0 0 0 2048", size, 1).unwrap();
+        let mut game = Game::new(&ui, board);
+        game.start_tiles = 0;
+        game.start();
+        ui.clear();
+        game.draw_frame();
+
+        let expected = "\
+Score: 0
+Best: 0
+    2     .     .     .     4
+    .     .     8     .     .
+   16     .     .     .  2048
+
+
+Moves: 0  Time: 0:00  Combo: 0  Top merge: 0  Best tile: 2048
+←,↑,→,↓, u, p, r, ? or q, / for help
+";
+        assert_eq!(ui.to_string(), expected);
+        assert_eq!(ui.value_at(27, ui.board_top() + 2), 2048);
+    }
+}